@@ -0,0 +1,44 @@
+// Rough timing of Decoder::read, since it runs on every byte we receive.
+// `cargo bench --bench decoder`
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use erhannis_misc::dencoder::{calcMsgSize, Decoder, DecoderT, Encoder};
+use heapless::Vec;
+
+const FRAMES: usize = 2000;
+
+/// Decodes FRAMES copies of a `msg_len` message, feeding the decoder `chunk` bytes at a time.
+fn bench<const CAPACITY: usize>(msg_len: usize, chunk: usize) -> Duration {
+  let msg: std::vec::Vec<u8> = (0..msg_len).map(|i| i as u8).collect();
+  let mut frame = vec![0u8; calcMsgSize(2, 4, msg_len)];
+  Encoder::<2, 4, ()>::write_plain(&msg, &mut frame).unwrap();
+
+  let mut decoder = Decoder::<2, 4, _, CAPACITY>::new_plain();
+  let mut out = Vec::<u8, CAPACITY>::new();
+  let start = Instant::now();
+  for _ in 0..FRAMES {
+    let mut done = false;
+    for c in frame.chunks(chunk) {
+      decoder.add(c).unwrap();
+      match decoder.read(&mut out) {
+        Ok(()) => done = true,
+        Err(nb::Error::WouldBlock) => (),
+        Err(nb::Error::Other(e)) => panic!("{:?}", e),
+      }
+    }
+    assert!(done);
+    black_box(&out);
+  }
+  start.elapsed() / FRAMES as u32
+}
+
+fn main() {
+  println!("CAPACITY  msg  chunk  time/frame");
+  for (msg_len, chunk) in [(16, 1), (16, 8), (200, 32), (500, 500)] {
+    println!("{:8} {:4} {:6}  {:?}", 1024, msg_len, chunk, bench::<1024>(msg_len, chunk));
+    println!("{:8} {:4} {:6}  {:?}", 16384, msg_len, chunk, bench::<16384>(msg_len, chunk));
+    println!("{:8} {:4} {:6}  {:?}", 65536, msg_len, chunk, bench::<65536>(msg_len, chunk));
+  }
+}
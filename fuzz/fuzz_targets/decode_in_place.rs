@@ -0,0 +1,47 @@
+// Arbitrary bytes into Decoder::decode_in_place, stepping through the buffer as a caller would.
+// Checks nothing's consumed past the end, and the message is inside the buffer.
+// `cargo fuzz run decode_in_place`
+
+#![no_main]
+
+use erhannis_misc::dencoder::transport::BufferSource;
+use erhannis_misc::dencoder::{Decoder, Framing};
+use libfuzzer_sys::fuzz_target;
+
+/// The first byte picks framing and FEC.
+fn scan<const L: usize, const C: usize>(data: &[u8]) {
+  let Some((&knobs, input)) = data.split_first() else {
+    return;
+  };
+  let framing = if knobs & 0x80 != 0 { Framing::Cobs } else { Framing::MagicByte };
+  let fec = ((knobs >> 5) & 0x03) as usize;
+  let mut decoder = Decoder::<L, C, BufferSource<0>, 0>::new_plain().with_framing(framing).with_fec(fec);
+  let mut buf = input.to_vec();
+  let mut at = 0;
+  while at < buf.len() {
+    let rest = buf.len() - at;
+    match decoder.decode_in_place(&mut buf[at..]) {
+      Ok((msg, consumed)) => {
+        assert!(consumed > 0 && consumed <= rest);
+        assert!(msg.len() < consumed);
+        at += consumed;
+      },
+      Err(e) if e.error == nb::Error::WouldBlock => {
+        assert!(e.consumed <= rest);
+        break;
+      },
+      Err(e) => {
+        assert!(e.consumed > 0 && e.consumed <= rest);
+        at += e.consumed;
+      },
+    }
+  }
+}
+
+fuzz_target!(|data: &[u8]| {
+  scan::<1, 0>(data);
+  scan::<1, 1>(data);
+  scan::<2, 4>(data);
+  scan::<3, 7>(data);
+  scan::<8, 32>(data);
+});
@@ -0,0 +1,39 @@
+// Arbitrary bytes into Decoder::read, in arbitrary pieces, with a spread of parameters.  Bad frames
+// are fine; panics aren't.
+// `cargo fuzz run decoder_read`
+
+#![no_main]
+
+use erhannis_misc::dencoder::{Decoder, DecoderT, Framing};
+use heapless::Vec;
+use libfuzzer_sys::fuzz_target;
+
+/// The first byte picks framing, FEC and how many bytes go in at a time.
+fn feed<const L: usize, const C: usize>(data: &[u8]) {
+  let Some((&knobs, input)) = data.split_first() else {
+    return;
+  };
+  let framing = if knobs & 0x80 != 0 { Framing::Cobs } else { Framing::MagicByte };
+  let fec = ((knobs >> 5) & 0x03) as usize;
+  let chunk = (knobs & 0x1F) as usize + 1;
+  let mut decoder = Decoder::<L, C, _, 512>::new_plain().with_framing(framing).with_fec(fec);
+  let mut out = Vec::<u8, 256>::new();
+  for c in input.chunks(chunk) {
+    decoder.add(c).unwrap();
+    loop {
+      match decoder.read(&mut out) {
+        Ok(()) => assert!(out.len() <= 256),
+        Err(nb::Error::WouldBlock) => break,
+        Err(nb::Error::Other(_)) => (),
+      }
+    }
+  }
+}
+
+fuzz_target!(|data: &[u8]| {
+  feed::<1, 0>(data);
+  feed::<1, 1>(data);
+  feed::<2, 4>(data);
+  feed::<3, 7>(data);
+  feed::<8, 32>(data);
+});
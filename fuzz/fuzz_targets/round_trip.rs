@@ -0,0 +1,63 @@
+// Arbitrary junk, then real frames: every frame must come out, whatever the junk did to the Decoder
+// on the way.  The fuzzer can work out checksums, so the junk may hold frames of its own; those come
+// out first, and only the end is compared.
+// `cargo fuzz run round_trip`
+
+#![no_main]
+
+use erhannis_misc::dencoder::{calc_cobs_msg_size, calcMsgSize, Decoder, DecoderT, Encoder, Framing};
+use heapless::Vec;
+use libfuzzer_sys::fuzz_target;
+
+/// Zeros after the frames, enough to finish off any bogus frame still waiting on its body.
+const TAIL: usize = 512;
+
+fn frame(framing: Framing, msg: &[u8]) -> std::vec::Vec<u8> {
+  match framing {
+    Framing::MagicByte => {
+      let mut out = vec![0u8; calcMsgSize(2, 4, msg.len())];
+      Encoder::<2, 4, ()>::write_plain(msg, &mut out).unwrap();
+      out
+    },
+    Framing::Cobs => {
+      let mut out = vec![0u8; calc_cobs_msg_size(2, 4, msg.len())];
+      let n = Encoder::<2, 4, ()>::write_plain_cobs(msg, &mut out).unwrap();
+      out.truncate(n);
+      out
+    },
+  }
+}
+
+// The first byte picks framing and how much of the rest is junk; what's left is split into messages at 0xFF bytes.
+fuzz_target!(|data: &[u8]| {
+  let Some((&knobs, rest)) = data.split_first() else {
+    return;
+  };
+  let framing = if knobs & 0x80 != 0 { Framing::Cobs } else { Framing::MagicByte };
+  let (junk, payload) = rest.split_at(((knobs & 0x7F) as usize).min(rest.len()));
+  let msgs: std::vec::Vec<std::vec::Vec<u8>> = payload.split(|&b| b == 0xFF).filter(|m| m.len() <= 200).map(|m| m.to_vec()).collect();
+
+  let mut input = junk.to_vec();
+  if framing == Framing::Cobs {
+    input.push(0); // Junk may have left a COBS frame open; close it, or it'd swallow the first real one
+  }
+  for m in &msgs {
+    input.extend(frame(framing, m));
+  }
+  input.extend_from_slice(&[0; TAIL]);
+
+  let mut decoder = Decoder::<2, 4, _, 1024>::new_plain().with_framing(framing);
+  let mut out = Vec::<u8, 256>::new();
+  let mut got = vec![];
+  for &b in &input {
+    decoder.add(&[b]).unwrap();
+    loop {
+      match decoder.read(&mut out) {
+        Ok(()) => got.push(out.to_vec()),
+        Err(nb::Error::WouldBlock) => break,
+        Err(nb::Error::Other(_)) => (),
+      }
+    }
+  }
+  assert!(got.ends_with(&msgs), "{:02x?} from {:02x?}", got, msgs);
+});
@@ -1,91 +1,792 @@
-// de/encode messages to length-prefixed checksummed byte streams.
+//! De/encode messages to length-prefixed checksummed byte streams.
+//!
+//! # Wire format
+//!
+//! A frame is, in order:
+//!
+//! | Field           | Bytes                     | Contents                                       |
+//! |-----------------|---------------------------|------------------------------------------------|
+//! | magic           | 1                         | 0xA9, or 0xA5 if a header follows              |
+//! | header          | 0 or `FRAME_HEADER_BYTES` | Only after 0xA5; see below                     |
+//! | length          | LEN_PREFIX_BYTES          | Message length, big-endian unless configured   |
+//! | length checksum | LEN_PREFIX_BYTES          | CK over header and length                      |
+//! | message         | length                    |                                                |
+//! | checksum        | CHECKSUM_BYTES            | CK over message                                |
+//!
+//! With FEC (`with_fec(t)`), message and checksum together are split into blocks of up to 255 - 2t
+//! bytes, each followed by 2t bytes of Reed-Solomon parity (see `fec` for the exact code).  Each
+//! block can then have up to t bad bytes repaired.  The header and length aren't covered; a bad
+//! length still fails its checksum and drops the frame.
+//!
+//! Checksums are the first bytes of the algorithm's big-endian output, zero-padded if the output is
+//! shorter (see `checksum`).  0xA9 frames are the original format, and carry nothing saying how they
+//! were encoded; the receiver just has to be built with the same parameters.
+//!
+//! The header says how the sender was built, so a mismatched receiver can report that rather than
+//! a stream of checksum failures:
+//!
+//! | Byte | Bits | Contents                                   |
+//! |------|------|--------------------------------------------|
+//! | 0    | 7-6  | Format version, currently `FORMAT_VERSION` |
+//! | 0    | 5-3  | `Checksum::ID` of CK                       |
+//! | 0    | 2-0  | LEN_PREFIX_BYTES - 1                       |
+//! | 1    | 7    | 1 if the body has FEC parity               |
+//! | 1    | 6    | 1 if the length is little-endian           |
+//! | 1    | 5-0  | CHECKSUM_BYTES                             |
+//!
+//! Encoders send the header if built `with_header(true)`.  Decoders accept frames either way, and
+//! drop headered frames that don't match their own parameters with `DencoderError::FormatMismatch`.
+//! Since the header's only protected by the checksums, that's only reported once they check out as
+//! computed the sender's way (so only for the checksums in `checksum`, or CK, and not with FEC and a
+//! LEN_PREFIX_BYTES under 4); otherwise the 0xA5 is taken to be noise.
+//! The header only says whether there's FEC, not how much; that has to be agreed on beforehand.
+//!
+//! With `Framing::Cobs`, the whole frame above is COBS-encoded and followed by a 0x00.
+//!
+//! Encoders and Decoders count what they've sent and received, and what went wrong, in `stats`;
+//! give them a `RateMeter` (`with_rate_meter`) for frames per second as well.
+//!
+//! Encoders write to a `FrameSink` and Decoders read from a `FrameSource` (see `transport`); a closure
+//! will do for either.  `Encoder::new_plain` and `Decoder::new_plain` use a slice and a buffer you `add` to.
+//! With the `embedded-io` feature, `serial` has a sink and source for `embedded_io` serial ports;
+//! with `embedded-io-async` or `tokio`, `asynch` has async versions of the Encoder and Decoder; and
+//! with `std`, `framed` wraps `std::io` readers and writers.  With `tokio-util`, `codec` has a
+//! `tokio_util::codec` Encoder/Decoder.  `stream` sends a message too big to hold in memory, a chunk at a time.
+//!
+//! On top of an Encoder and Decoder, `reliable` adds acknowledgements and retransmission,
+//! `fragment` splits messages too big for one frame across several, and `channel` carries several
+//! independent streams of messages over one link.  `auth` authenticates messages with a shared key.
+//! With `postcard`, `typed` sends and receives serde types, and with `lz4`, `compress` compresses messages.
+
+pub mod checksum;
+pub mod fec;
+pub mod fragment;
+pub mod reliable;
+#[cfg(any(feature = "embedded-io-async", feature = "tokio"))]
+pub mod asynch;
+pub mod auth;
+pub mod channel;
+#[cfg(feature = "tokio-util")]
+pub mod codec;
+#[cfg(feature = "lz4")]
+pub mod compress;
+#[cfg(feature = "std")]
+pub mod framed;
+#[cfg(feature = "embedded-io")]
+pub mod serial;
+pub mod stream;
+pub mod transport;
+#[cfg(feature = "postcard")]
+pub mod typed;
+
+use core::convert::Infallible;
+use core::fmt;
+use core::marker::PhantomData;
 
 use heapless::{CapacityError, Vec};
 use log::{error, trace};
-use sha2::{Digest, Sha256};
 
+use crate::rate_meter::RateMeter;
 use crate::utils::to_hex_string;
+use checksum::{Checksum, Sha256Checksum, MAX_CHECKSUM_BYTES};
+use stream::StreamedFrame;
+use transport::{BufferSource, FrameSink, FrameSource, SliceSink};
 
 const MAGIC_BYTE: u8 = 0b10101001; // 0xA9 // Sorta arbitrary, seems harder to get on accident
+const MAGIC_BYTE_HEADERED: u8 = 0b10100101; // 0xA5
+const COBS_DELIMITER: u8 = 0x00;
+
+/// Version of the frame header layout; see the module docs.
+pub const FORMAT_VERSION: u8 = 1;
+/// Length of the frame header, when there is one.
+pub const FRAME_HEADER_BYTES: usize = 2;
+/// Checksum bytes a frame whose header isn't ours has to pass to be reported as FormatMismatch, rather
+/// than taken for noise; noise gets through one time in 2^32.
+const FOREIGN_CHECKED_BYTES: usize = 4;
 
 pub enum TransmissionStatus {
   Complete,
   Partial(usize),
 }
 
+/// How frames are delimited on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Framing {
+  /// Frames start with a magic byte, and the decoder relies on the checksums to tell real frame starts
+  /// from magic bytes that happen to turn up in the data.
+  #[default]
+  MagicByte,
+  /// The usual frame (magic byte and all) is COBS-encoded and followed by a 0x00, which can't appear
+  /// anywhere else, so frame boundaries are unambiguous.  Costs 2 bytes, plus 1 per 254.
+  Cobs,
+}
+
+/// Byte order of the length prefix.  Checksums are always big-endian.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Endianness {
+  #[default]
+  Big,
+  Little,
+}
+
+/// The parameters a frame was encoded with, as carried in its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameFormat {
+  pub version: u8,
+  pub checksum_id: u8,
+  pub len_prefix_bytes: usize,
+  pub checksum_bytes: usize,
+  pub endianness: Endianness,
+  pub fec: bool,
+}
+
+impl FrameFormat {
+  /// The current-version format for the given parameters.
+  pub const fn new(len_prefix_bytes: usize, checksum_bytes: usize, checksum_id: u8, endianness: Endianness, fec: bool) -> FrameFormat {
+    return FrameFormat {
+      version: FORMAT_VERSION,
+      checksum_id: checksum_id,
+      len_prefix_bytes: len_prefix_bytes,
+      checksum_bytes: checksum_bytes,
+      endianness: endianness,
+      fec: fec,
+    };
+  }
+
+  pub const fn to_bytes(&self) -> [u8; FRAME_HEADER_BYTES] {
+    return [
+      ((self.version & 0x03) << 6) | ((self.checksum_id & 0x07) << 3) | ((self.len_prefix_bytes.wrapping_sub(1) as u8) & 0x07),
+      ((self.fec as u8) << 7) | ((matches!(self.endianness, Endianness::Little) as u8) << 6) | ((self.checksum_bytes as u8) & 0x3F),
+    ];
+  }
+
+  pub const fn from_bytes(bytes: [u8; FRAME_HEADER_BYTES]) -> FrameFormat {
+    return FrameFormat {
+      version: bytes[0] >> 6,
+      checksum_id: (bytes[0] >> 3) & 0x07,
+      len_prefix_bytes: (bytes[0] & 0x07) as usize + 1,
+      checksum_bytes: (bytes[1] & 0x3F) as usize,
+      endianness: if bytes[1] & 0x40 != 0 { Endianness::Little } else { Endianness::Big },
+      fec: bytes[1] & 0x80 != 0,
+    };
+  }
+}
+
+/// Everything that can go wrong in `Encoder::write` or `Decoder::read`.
+/// `E` is the error type of the sink/source (see `transport`), passed through as `Transport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DencoderError<E> {
+  /// The message is too long for its length to fit in LEN_PREFIX_BYTES.
+  MessageTooLong,
+  /// `write_plain` was given an `out` of the wrong size; see `calcMsgSize`.
+  OutputSizeMismatch,
+  /// The Decoder's internal buffer (BUF_SIZE) ran out of space.
+  BufferFull,
+  /// The incoming message is bigger than the buffer passed to `read`.  The frame was dropped.
+  MessageTooBig,
+  /// The length prefix failed its checksum.  The frame was dropped.
+  LengthChecksum,
+  /// The message failed its checksum.  The frame was dropped.
+  MessageChecksum,
+  /// A COBS frame didn't decode to a whole frame.  It was dropped.
+  BadFraming,
+  /// A FEC block had more bad bytes than it could repair.  The frame was dropped.
+  Uncorrectable,
+  /// The frame's header says it was encoded with different parameters (given here).  It was dropped.
+  FormatMismatch(FrameFormat),
+  /// The sink or source returned an error.
+  Transport(E),
+  /// The stream ended (e.g. the other end hung up).  Only from readers that can tell; see `asynch`.
+  Eof,
+  /// A `StreamedFrame` was given more or less message than `begin` said, or used after it finished or failed.
+  BadStream,
+  /// The frame arrived intact but failed its authentication tag; see `auth`.  It was dropped.
+  Unauthenticated,
+  /// The frame's counter isn't newer than the last one accepted, so it may be a replay; see `auth`.  It was dropped.
+  Replayed,
+  /// The AuthEncoder's counter has run out, so it can't send anything more under this key; see `auth`.
+  CounterExhausted,
+  /// The frame arrived intact but didn't decompress; see `compress`.  It was dropped.
+  Decompression,
+}
+
+impl <E> DencoderError<E> {
+  /// Splits off the transport's own error, leaving any other as a DencoderError<Infallible>.
+  pub(crate) fn split_transport(self) -> Result<DencoderError<Infallible>, E> {
+    return match self {
+      DencoderError::MessageTooLong => Ok(DencoderError::MessageTooLong),
+      DencoderError::OutputSizeMismatch => Ok(DencoderError::OutputSizeMismatch),
+      DencoderError::BufferFull => Ok(DencoderError::BufferFull),
+      DencoderError::MessageTooBig => Ok(DencoderError::MessageTooBig),
+      DencoderError::LengthChecksum => Ok(DencoderError::LengthChecksum),
+      DencoderError::MessageChecksum => Ok(DencoderError::MessageChecksum),
+      DencoderError::BadFraming => Ok(DencoderError::BadFraming),
+      DencoderError::Uncorrectable => Ok(DencoderError::Uncorrectable),
+      DencoderError::FormatMismatch(ff) => Ok(DencoderError::FormatMismatch(ff)),
+      DencoderError::Transport(e) => Err(e),
+      DencoderError::Eof => Ok(DencoderError::Eof),
+      DencoderError::BadStream => Ok(DencoderError::BadStream),
+      DencoderError::Unauthenticated => Ok(DencoderError::Unauthenticated),
+      DencoderError::Replayed => Ok(DencoderError::Replayed),
+      DencoderError::CounterExhausted => Ok(DencoderError::CounterExhausted),
+      DencoderError::Decompression => Ok(DencoderError::Decompression),
+    };
+  }
+}
+
+impl DencoderError<Infallible> {
+  /// For errors from an Encoder or Decoder whose transport can't fail, as the error type of one that can.
+  pub(crate) fn cast<E>(self) -> DencoderError<E> {
+    return match self {
+      DencoderError::MessageTooLong => DencoderError::MessageTooLong,
+      DencoderError::OutputSizeMismatch => DencoderError::OutputSizeMismatch,
+      DencoderError::BufferFull => DencoderError::BufferFull,
+      DencoderError::MessageTooBig => DencoderError::MessageTooBig,
+      DencoderError::LengthChecksum => DencoderError::LengthChecksum,
+      DencoderError::MessageChecksum => DencoderError::MessageChecksum,
+      DencoderError::BadFraming => DencoderError::BadFraming,
+      DencoderError::Uncorrectable => DencoderError::Uncorrectable,
+      DencoderError::FormatMismatch(ff) => DencoderError::FormatMismatch(ff),
+      DencoderError::Transport(e) => match e {},
+      DencoderError::Eof => DencoderError::Eof,
+      DencoderError::BadStream => DencoderError::BadStream,
+      DencoderError::Unauthenticated => DencoderError::Unauthenticated,
+      DencoderError::Replayed => DencoderError::Replayed,
+      DencoderError::CounterExhausted => DencoderError::CounterExhausted,
+      DencoderError::Decompression => DencoderError::Decompression,
+    };
+  }
+}
+
+impl <E: fmt::Display> fmt::Display for DencoderError<E> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    return match self {
+      DencoderError::MessageTooLong => write!(f, "message too long for length prefix"),
+      DencoderError::OutputSizeMismatch => write!(f, "output buffer size does not match frame size"),
+      DencoderError::BufferFull => write!(f, "decoder buffer full"),
+      DencoderError::MessageTooBig => write!(f, "incoming message too big for read buffer"),
+      DencoderError::LengthChecksum => write!(f, "length checksum failed"),
+      DencoderError::MessageChecksum => write!(f, "message checksum failed"),
+      DencoderError::BadFraming => write!(f, "malformed COBS frame"),
+      DencoderError::Uncorrectable => write!(f, "too many errors for FEC to repair"),
+      DencoderError::FormatMismatch(ff) => write!(f, "frame format mismatch: sender is v{} checksum {} LEN_PREFIX_BYTES {} CHECKSUM_BYTES {} {:?}-endian FEC {}", ff.version, ff.checksum_id, ff.len_prefix_bytes, ff.checksum_bytes, ff.endianness, ff.fec),
+      DencoderError::Transport(e) => write!(f, "transport error: {}", e),
+      DencoderError::Eof => write!(f, "end of stream"),
+      DencoderError::BadStream => write!(f, "streamed message length does not match begin"),
+      DencoderError::Unauthenticated => write!(f, "authentication tag mismatch"),
+      DencoderError::Replayed => write!(f, "frame counter not newer than the last; possible replay"),
+      DencoderError::CounterExhausted => write!(f, "message counter exhausted"),
+      DencoderError::Decompression => write!(f, "message failed to decompress"),
+    };
+  }
+}
+
+#[cfg(feature = "std")]
+impl <E: fmt::Debug + fmt::Display> std::error::Error for DencoderError<E> {}
+
 pub trait DecoderT {
-  fn read<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>) -> Result<(), nb::Error<()>>;
+  /// Error type of the underlying transport.
+  type Error;
+  fn read<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>) -> Result<(), nb::Error<DencoderError<Self::Error>>>;
 }
 
 pub trait EncoderT {
-  fn write(&mut self, msg: &[u8]) -> Result<(), ()>;
+  /// Error type of the underlying transport.
+  type Error;
+  /// Returns Err(WouldBlock) if `tx` couldn't take the whole frame yet; call `write` again with the same `msg` to resume.
+  fn write(&mut self, msg: &[u8]) -> Result<(), nb::Error<DencoderError<Self::Error>>>;
+  /// Like `write`, but the message is `parts` concatenated, e.g. a header and a payload, without copying them together first.
+  /// To resume, call again with the same `parts`.
+  fn write_vectored(&mut self, parts: &[&[u8]]) -> Result<(), nb::Error<DencoderError<Self::Error>>>;
 }
 
-pub struct Decoder<const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, STATE, const BUF_SIZE: usize> {
-  state: STATE,
-  incoming_message: Vec<u8, BUF_SIZE>,
-  before_rx: Option<fn(&mut STATE)>,
-  rx: fn(&mut STATE, &mut [u8]) -> Result<TransmissionStatus, nb::Error<()>>,
-  after_rx: Option<fn(&mut STATE)>,
+/// How FEC has done on the frames a Decoder has received; see `with_fec`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FecStats {
+  /// Frames that had bad bytes, all repaired.
+  pub corrected_frames: u32,
+  /// Bytes repaired, across all corrected frames.
+  pub corrected_bytes: u32,
+  /// Frames with more damage than FEC could repair (or that repaired wrong, and failed the checksum after).
+  pub uncorrectable_frames: u32,
 }
 
-pub struct Encoder<const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, STATE> {
-  state: STATE,
-  before_tx: Option<fn(&mut STATE)>,
-  tx: fn(&mut STATE, &[&[u8]]) -> Result<TransmissionStatus, nb::Error<()>>,
-  after_tx: Option<fn(&mut STATE)>,
+/// What an Encoder has sent; see `Encoder::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EncoderStats {
+  /// Frames all the way out to the sink.
+  pub frames: u32,
+  /// Bytes the sink took, framing and all.
+  pub bytes: u64,
+  /// Times the sink took less than it was offered, so the write returned WouldBlock.
+  pub partial_writes: u32,
+  /// Messages refused as too long for LEN_PREFIX_BYTES.
+  pub oversize_drops: u32,
+  /// Frames abandoned because the sink failed.
+  pub transport_errors: u32,
 }
 
-//RAINY Document format, maybe version it
-/// Important note: this class API handles complete messages, not just streams of bytes.
-/// LEN_PREFIX_BYTES means the width of the uint that can encode the length of the message, basically.
-/// CHECKSUM_BYTES means the number of bytes in the suffix checksum (assumed at most 32)
-/// Total packet length is 2*LEN_PREFIX_BYTES + message_length + CHECKSUM_BYTES
+/// What a Decoder has received, and how much of it was bad; see `Decoder::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecoderStats {
+  /// Messages delivered.
+  pub frames: u32,
+  /// Bytes taken from the source, good or bad.
+  pub bytes: u64,
+  /// Bytes thrown away looking for the start of a frame: noise between frames, and the magic bytes of bad ones.
+  pub skipped_bytes: u64,
+  /// Bad frames after which the bytes behind their magic byte were rescanned for a real one (`Framing::MagicByte` only).
+  pub resyncs: u32,
+  pub length_checksum_failures: u32,
+  pub message_checksum_failures: u32,
+  /// Frames dropped for being too big for the caller's buffer or BUF_SIZE.
+  pub oversize_drops: u32,
+  /// Headered frames from a sender built differently.
+  pub format_mismatches: u32,
+  /// COBS frames that didn't decode.
+  pub bad_framing: u32,
+  pub fec: FecStats,
+}
+
+impl DecoderStats {
+  /// Counts a frame dropped with `e`.  Transport errors aren't the frame's fault, so aren't counted.
+  fn count_failure<E>(&mut self, e: &DencoderError<E>) {
+    match e {
+      DencoderError::LengthChecksum => self.length_checksum_failures += 1,
+      DencoderError::MessageChecksum => self.message_checksum_failures += 1,
+      DencoderError::MessageTooBig | DencoderError::BufferFull => self.oversize_drops += 1,
+      DencoderError::FormatMismatch(_) => self.format_mismatches += 1,
+      DencoderError::BadFraming => self.bad_framing += 1,
+      _ => (), // Uncorrectable is in `fec`
+    };
+  }
+}
+
+/// Where the Decoder is in the frame it's currently receiving.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RxPhase {
+  Delimiter, // COBS only: collecting encoded bytes up to the 0x00
+  Magic,
+  Header,
+  ForeignHeader { head_len: usize }, // Header isn't ours; waiting for enough of the frame to check it the sender's way
+  Length,
+  LengthChecksum,
+  Body { len: usize },
+  Checksum { len: usize },
+}
+
+pub struct Decoder<const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SOURCE, const BUF_SIZE: usize, CK = Sha256Checksum> {
+  source: SOURCE,
+  incoming_message: Vec<u8, BUF_SIZE>, // Raw bytes of the frame in progress
+  phase: RxPhase,
+  header_bytes: usize, // Header length of the frame in progress, once we've seen its magic byte
+  framing: Framing,
+  endianness: Endianness,
+  fec_parity: usize, // Parity bytes per FEC block, or 0 for no FEC
+  stats: DecoderStats,
+  rate_meter: Option<RateMeter>,
+  checksum: PhantomData<CK>,
+}
 
-impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, STATE> Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, STATE> {
-  pub fn new(state: STATE, before_tx: Option<fn(&mut STATE)>, tx: fn(&mut STATE, &[&[u8]]) -> Result<TransmissionStatus, nb::Error<()>>, after_tx: Option<fn(&mut STATE)>) -> Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, STATE> {
+pub struct Encoder<const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SINK, CK = Sha256Checksum> {
+  sink: SINK,
+  framing: Framing,
+  endianness: Endianness,
+  fec_parity: usize, // Parity bytes per FEC block, or 0 for no FEC
+  header: bool,
+  progress: usize, // Bytes of the current frame already accepted by the sink
+  stats: EncoderStats,
+  rate_meter: Option<RateMeter>,
+  checksum: PhantomData<CK>,
+}
+
+/// Important note: this class API handles complete messages, not just streams of bytes.
+/// LEN_PREFIX_BYTES means the width of the uint that can encode the length of the message, basically (1 to 8).
+/// CHECKSUM_BYTES means the number of bytes in the suffix checksum (at most checksum::MAX_CHECKSUM_BYTES)
+/// CK is the checksum algorithm, used for both checksums; see `checksum`.  Defaults to truncated SHA-256.
+/// SINK is where the bytes go; see `transport`.
+/// Total packet length is 1 + 2*LEN_PREFIX_BYTES + message_length + CHECKSUM_BYTES,
+/// plus FRAME_HEADER_BYTES `with_header` and parity `with_fec` (more with `Framing::Cobs`; see `calc_cobs_msg_size`).
+/// `frame_size` accounts for the options that are set.
+/// The full format is described in the module docs.
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SINK: FrameSink, CK: Checksum> Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SINK, CK> {
+  pub fn new(sink: SINK) -> Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SINK, CK> {
     return Encoder {
-      state: state,
-      before_tx: before_tx,
-      tx: tx,
-      after_tx: after_tx,
+      sink: sink,
+      framing: Framing::MagicByte,
+      endianness: Endianness::Big,
+      fec_parity: 0,
+      header: false,
+      progress: 0,
+      stats: EncoderStats::default(),
+      rate_meter: None,
+      checksum: PhantomData,
+    };
+  }
+
+  /**
+   * Sets how frames are delimited.  The Decoder on the other end must match.
+   */
+  pub fn with_framing(mut self, framing: Framing) -> Self {
+    self.framing = framing;
+    return self;
+  }
+
+  /**
+   * Sets the byte order of the length prefix.  The Decoder on the other end must match.
+   */
+  pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+    self.endianness = endianness;
+    return self;
+  }
+
+  /**
+   * Adds Reed-Solomon parity to each frame's message and checksum, so the Decoder can repair up to
+   * `correctable` bad bytes per 255-byte block, at the cost of 2*`correctable` bytes per block.
+   * 0 turns it off.  The Decoder on the other end must match.
+   * Panics if `correctable` is more than `fec::MAX_CORRECTABLE`.
+   */
+  pub fn with_fec(mut self, correctable: usize) -> Self {
+    assert!(correctable <= fec::MAX_CORRECTABLE, "with_fec: at most {} correctable bytes", fec::MAX_CORRECTABLE);
+    self.fec_parity = 2 * correctable;
+    return self;
+  }
+
+  /**
+   * Size of the frame `write` would send for a `msg_bytes`-long message, not counting COBS.
+   */
+  pub fn frame_size(&self, msg_bytes: usize) -> usize {
+    let header = if self.header { FRAME_HEADER_BYTES } else { 0 };
+    return 1 + header + 2 * LEN_PREFIX_BYTES + fec::encoded_len(msg_bytes + CHECKSUM_BYTES, self.fec_parity);
+  }
+
+  /**
+   * Sets whether frames carry a header describing LEN_PREFIX_BYTES, CHECKSUM_BYTES, and CK, so a
+   * mismatched Decoder can tell.  Any Decoder can read either kind.
+   */
+  pub fn with_header(mut self, header: bool) -> Self {
+    self.header = header;
+    return self;
+  }
+
+  /**
+   * Counts each frame sent on `meter` too; call its `check` or `measure` (via `rate_meter_mut`) for frames per second.
+   */
+  pub fn with_rate_meter(mut self, meter: RateMeter) -> Self {
+    self.rate_meter = Some(meter);
+    return self;
+  }
+
+  pub fn rate_meter_mut(&mut self) -> Option<&mut RateMeter> {
+    return self.rate_meter.as_mut();
+  }
+
+  /// Counts of frames and bytes sent, and what went wrong, since the Encoder was made.
+  pub fn stats(&self) -> EncoderStats {
+    return self.stats;
+  }
+
+  pub fn sink(&self) -> &SINK {
+    return &self.sink;
+  }
+
+  pub fn sink_mut(&mut self) -> &mut SINK {
+    return &mut self.sink;
+  }
+
+  /**
+   * Abandons a frame left half-sent by a `write` that returned WouldBlock, so the next `write`
+   * starts a fresh frame.  The receiver will see the truncated frame fail and resync.
+   */
+  pub fn clear(&mut self) {
+    self.progress = 0;
+  }
+
+  /**
+   * Starts a frame for a `len`-byte message that you then hand over a chunk at a time, for messages
+   * too big to have in memory all at once (firmware images, say); see `StreamedFrame`.
+   * Abandons any frame left half-sent, as `clear`.
+   */
+  pub fn begin(&mut self, len: usize) -> Result<StreamedFrame<'_, LEN_PREFIX_BYTES, CHECKSUM_BYTES, SINK, CK>, DencoderError<SINK::Error>> {
+    let head = match self.frame_head(len) {
+      Some(head) => head,
+      None => {
+        self.stats.oversize_drops += 1;
+        return Err(DencoderError::MessageTooLong);
+      },
+    };
+    self.clear();
+    return Ok(StreamedFrame::new(self, head, len));
+  }
+
+  /**
+   * Counts a frame that's all the way out.
+   */
+  fn count_frame(&mut self) {
+    self.stats.frames += 1;
+    if let Some(meter) = self.rate_meter.as_mut() {
+      meter.inc();
+    }
+  }
+
+  /**
+   * Everything before the message, for a `msg_len`-byte message.  None if that's too long for LEN_PREFIX_BYTES.
+   */
+  fn frame_head(&self, msg_len: usize) -> Option<FrameHead<LEN_PREFIX_BYTES>> {
+    // (checked_shr, since a full-width shift would overflow)
+    if (msg_len as u64).checked_shr(8 * LEN_PREFIX_BYTES as u32).unwrap_or(0) != 0 {
+      return None;
+    }
+    let len_buf: [u8; LEN_PREFIX_BYTES] = encode_len(msg_len, self.endianness); // Does not include the checksum
+    let header = FrameFormat::new(LEN_PREFIX_BYTES, CHECKSUM_BYTES, CK::ID, self.endianness, self.fec_parity > 0).to_bytes();
+    let (magic, header_len) = if self.header {
+      (MAGIC_BYTE_HEADERED, FRAME_HEADER_BYTES)
+    } else {
+      (MAGIC_BYTE, 0)
     };
+    let mut len_ck = CK::default();
+    len_ck.update(&header[..header_len]);
+    len_ck.update(&len_buf);
+    return Some(FrameHead {
+      magic: magic,
+      header: header,
+      header_len: header_len,
+      len_buf: len_buf,
+      len_hash: len_ck.finalize(),
+    });
   }
 
+  /**
+   * Passes `pieces` to the sink as the next part of the frame, `offset` bytes in, and moves `offset` past them.
+   * Skips whatever a previous call already got out.
+   */
+  fn send(&mut self, offset: &mut usize, pieces: &mut [&[u8]]) -> Result<(), nb::Error<SINK::Error>> {
+    let start = *offset;
+    *offset += pieces.iter().map(|p| p.len()).sum::<usize>();
+    if self.progress >= *offset {
+      return Ok(());
+    }
+    let mut skip = self.progress - start;
+    for p in pieces.iter_mut() {
+      let n = skip.min(p.len());
+      *p = &p[n..];
+      skip -= n;
+    }
+    let status = self.sink.tx(pieces).inspect_err(|e| {
+      if matches!(e, nb::Error::WouldBlock) {
+        self.stats.partial_writes += 1;
+      }
+    })?;
+    match status {
+      TransmissionStatus::Complete => {
+        self.stats.bytes += (*offset - self.progress) as u64;
+        self.progress = *offset;
+        return Ok(());
+      },
+      TransmissionStatus::Partial(n) => {
+        self.stats.bytes += n as u64;
+        self.stats.partial_writes += 1;
+        self.progress += n;
+        return Err(nb::Error::WouldBlock);
+      },
+    };
+  }
+
+  /**
+   * COBS-encodes `pieces` (as though they were one buffer, continuing on from earlier calls) and sends them.
+   * Non-zero bytes pile up in `run` until a zero or the 254th byte ends it; then it goes out as its
+   * length code plus the run itself.  Call `finish_cobs` after the last piece.
+   */
+  fn send_cobs(&mut self, offset: &mut usize, run: &mut CobsRun, pieces: &[&[u8]]) -> Result<(), nb::Error<SINK::Error>> {
+    for &b in pieces.iter().flat_map(|p| p.iter()) {
+      if b == 0 {
+        self.send_cobs_run(offset, run)?;
+      } else {
+        run.bytes[run.len] = b;
+        run.len += 1;
+        if run.len == run.bytes.len() {
+          self.send_cobs_run(offset, run)?;
+        }
+      }
+    }
+    return Ok(());
+  }
+
+  fn send_cobs_run(&mut self, offset: &mut usize, run: &mut CobsRun) -> Result<(), nb::Error<SINK::Error>> {
+    let len = run.len;
+    run.len = 0;
+    return self.send(offset, &mut [&[len as u8 + 1], &run.bytes[..len]]);
+  }
+
+  /**
+   * Sends the last COBS run, and the delimiter.
+   */
+  fn finish_cobs(&mut self, offset: &mut usize, run: &mut CobsRun) -> Result<(), nb::Error<SINK::Error>> {
+    self.send_cobs_run(offset, run)?;
+    return self.send(offset, &mut [&[COBS_DELIMITER]]);
+  }
+
+  /**
+   * Hands the frame (`head`, then the message `parts`, then `msg_checksum`; see `write_vectored`) to
+   * `sink` in order, a few pieces at a time.  With FEC, the message and checksum are split into
+   * blocks, each followed by its parity.
+   */
+  fn emit_frame(head: [&[u8]; 4], parts: &[&[u8]], msg_checksum: &[u8], fec_parity: usize, mut sink: impl FnMut(&mut [&[u8]]) -> Result<(), nb::Error<SINK::Error>>) -> Result<(), nb::Error<SINK::Error>> {
+    let body = || parts.iter().copied().chain(core::iter::once(msg_checksum));
+    if fec_parity == 0 {
+      let mut batch = Batch::new();
+      for piece in head.into_iter().chain(body()) {
+        batch.push(piece, &mut sink)?;
+      }
+      return batch.flush(&mut sink);
+    }
+    sink(&mut head.clone())?;
+    let total: usize = body().map(|p| p.len()).sum();
+    let generator = fec::generator(fec_parity);
+    let mut parity = [0u8; fec::MAX_PARITY_BYTES];
+    let mut start = 0;
+    while start < total {
+      let end = (start + fec::BLOCK_BYTES - fec_parity).min(total);
+      // The pieces of the body falling in start..end
+      let block = || body().scan(0, |base, piece| {
+        let b = *base;
+        *base += piece.len();
+        return Some(clamp_slice(piece, b, start, end));
+      }).filter(|p| !p.is_empty());
+      parity[..fec_parity].fill(0);
+      for piece in block() {
+        fec::update_parity(&generator, piece, &mut parity[..fec_parity]);
+      }
+      let mut batch = Batch::new();
+      for piece in block() {
+        batch.push(piece, &mut sink)?;
+      }
+      batch.push(&parity[..fec_parity], &mut sink)?;
+      batch.flush(&mut sink)?;
+      start = end;
+    }
+    return Ok(());
+  }
+}
+
+/// Pieces of a frame waiting to go to the sink together, so it gets a few calls rather than one per piece.
+struct Batch<'p> {
+  pieces: [&'p [u8]; 8],
+  len: usize,
+}
+
+impl <'p> Batch<'p> {
+  fn new() -> Batch<'p> {
+    return Batch {
+      pieces: [&[]; 8],
+      len: 0,
+    };
+  }
+
+  fn push<E>(&mut self, piece: &'p [u8], sink: &mut impl FnMut(&mut [&[u8]]) -> Result<(), nb::Error<E>>) -> Result<(), nb::Error<E>> {
+    if self.len == self.pieces.len() {
+      self.flush(sink)?;
+    }
+    self.pieces[self.len] = piece;
+    self.len += 1;
+    return Ok(());
+  }
+
+  fn flush<E>(&mut self, sink: &mut impl FnMut(&mut [&[u8]]) -> Result<(), nb::Error<E>>) -> Result<(), nb::Error<E>> {
+    if self.len > 0 {
+      sink(&mut self.pieces[..self.len])?;
+      self.len = 0;
+    }
+    return Ok(());
+  }
+}
+
+/// Magic byte, header, length, and length checksum, as `Encoder::frame_head` works them out.
+struct FrameHead<const LEN_PREFIX_BYTES: usize> {
+  magic: u8,
+  header: [u8; FRAME_HEADER_BYTES],
+  header_len: usize, // 0 if the frame has no header
+  len_buf: [u8; LEN_PREFIX_BYTES],
+  len_hash: [u8; MAX_CHECKSUM_BYTES],
+}
+
+impl <const LEN_PREFIX_BYTES: usize> FrameHead<LEN_PREFIX_BYTES> {
+  fn pieces(&self) -> [&[u8]; 4] {
+    return [
+      core::slice::from_ref(&self.magic),
+      &self.header[..self.header_len],
+      &self.len_buf,
+      &self.len_hash[0..LEN_PREFIX_BYTES],
+    ];
+  }
+}
+
+/// A COBS run being collected until we know how long it is; see `Encoder::send_cobs`.
+#[derive(Clone, Copy)]
+struct CobsRun {
+  bytes: [u8; 254],
+  len: usize,
+}
+
+/// The part of `piece` (which starts `base` bytes into some larger buffer) that falls within start..end.
+fn clamp_slice(piece: &[u8], base: usize, start: usize, end: usize) -> &[u8] {
+  let s = start.clamp(base, base + piece.len()) - base;
+  let e = end.clamp(base, base + piece.len()) - base;
+  return &piece[s..e];
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SINK, CK: Checksum> Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SINK, CK> {
   /**
    * Wrap `msg` in length prefix and checksums (and whatever other processing is added in the future)
-   * and write it to `out`, skipping all the callbacks and such that the full Encoder has.
+   * and write it to `out`, no Encoder needed.  (SINK is ignored; `Encoder::<L, C, ()>::write_plain` will do.)
    * Note that if not `out.len() == calcMsgSize(LEN_PREFIX_BYTES, CHECKSUM_BYTES, msg.len())`,
-   * we return Err(()).
+   * we return Err(DencoderError::OutputSizeMismatch).
    */ //CHECK Maybe Vec out, not [u8]?
-  pub fn write_plain(msg: &[u8], out: &mut [u8]) -> Result<(), ()> { //THINK Should probably have proper ok/err types
+  pub fn write_plain(msg: &[u8], out: &mut [u8]) -> Result<(), DencoderError<Infallible>> {
     // Makes a new Encoder internally to write bytes into `out`
     //RAINY Mmmmaaaybe generic consts, so I can precalc the output size?  Actually, msg.len() maybe not const huh
     let size_out = calcMsgSize(LEN_PREFIX_BYTES, CHECKSUM_BYTES, msg.len());
     if size_out != out.len() {
-      return Err(());
-    }
-    let mut enc: Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, (usize, &mut [u8])> = Encoder {
-      state: (0, out),
-      before_tx: None,
-      tx: |state, buffers| {
-        //THINK We might just be able to send each individually, in this case
-        let mut offset = state.0;
-        for b in buffers {
-            let len = b.len();
-            //THINK I *believe* this should be guaranteed not to happen, if the other code is correct?
-            // if offset + len > MSG_SIZE {
-            //   error!("DBG ERR MSG TOO BIG");
-            // }
-            state.1[offset..offset + len].copy_from_slice(b);
-            offset += len;
-        }
-        state.0 = offset;
-        return Ok(TransmissionStatus::Complete);
-      },
-      after_tx: None,
+      return Err(DencoderError::OutputSizeMismatch);
+    }
+    let mut enc = Encoder::<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SliceSink, CK>::new_plain(out);
+    return match enc.write(msg) {
+      Ok(()) => Ok(()),
+      Err(nb::Error::Other(e)) => Err(e),
+      Err(nb::Error::WouldBlock) => Err(DencoderError::OutputSizeMismatch), // Can't happen; we checked the size
+    };
+  }
+
+  /**
+   * Like `write_plain`, but COBS-framed (see `Framing::Cobs`).  The encoded size depends on the
+   * content, so `out` just needs to be at least `calc_cobs_msg_size(LEN_PREFIX_BYTES, CHECKSUM_BYTES, msg.len())`
+   * long; returns the number of bytes written.
+   */
+  pub fn write_plain_cobs(msg: &[u8], out: &mut [u8]) -> Result<usize, DencoderError<Infallible>> {
+    if out.len() < calc_cobs_msg_size(LEN_PREFIX_BYTES, CHECKSUM_BYTES, msg.len()) {
+      return Err(DencoderError::OutputSizeMismatch);
+    }
+    let mut enc = Encoder::<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SliceSink, CK>::new_plain(out).with_framing(Framing::Cobs);
+    return match enc.write(msg) {
+      Ok(()) => Ok(enc.written()),
+      Err(nb::Error::Other(e)) => Err(e),
+      Err(nb::Error::WouldBlock) => Err(DencoderError::OutputSizeMismatch), // Can't happen; we checked the size
     };
-    return enc.write(msg);
+  }
+}
+
+impl <'a, const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, CK: Checksum> Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SliceSink<'a>, CK> {
+  /**
+   * Returns an Encoder that writes frames into `out`, one after another; see `written`.
+   * Once `out` is full, `write` returns WouldBlock.
+   */
+  pub fn new_plain(out: &'a mut [u8]) -> Self {
+    return Encoder::new(SliceSink::new(out));
+  }
+
+  /// Number of bytes written into `out` so far.
+  pub fn written(&self) -> usize {
+    return self.sink.written();
   }
 }
 
@@ -101,7 +802,60 @@ pub const fn calcMsgSize(LEN_PREFIX_BYTES: usize, CHECKSUM_BYTES: usize, MSG_BYT
   return r;
 }
 
-impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, STATE> EncoderT for Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, STATE> {
+/// Largest size a COBS-framed message can come out to; see `calcMsgSize` and `Framing::Cobs`.
+pub const fn calc_cobs_msg_size(len_prefix_bytes: usize, checksum_bytes: usize, msg_bytes: usize) -> usize {
+  let n = calcMsgSize(len_prefix_bytes, checksum_bytes, msg_bytes);
+  return n + (n / 254) + 1 + 1; // code bytes, delimiter
+}
+
+/// `len` as LEN_PREFIX_BYTES bytes; the caller has already checked it fits.
+fn encode_len<const LEN_PREFIX_BYTES: usize>(len: usize, endianness: Endianness) -> [u8; LEN_PREFIX_BYTES] {
+  let mut len_buf = [0u8; LEN_PREFIX_BYTES];
+  let mut l = len as u64;
+  for i in 0..LEN_PREFIX_BYTES.min(8) {
+    match endianness {
+      Endianness::Big => len_buf[LEN_PREFIX_BYTES - 1 - i] = l as u8,
+      Endianness::Little => len_buf[i] = l as u8,
+    };
+    l >>= 8;
+  }
+  return len_buf;
+}
+
+/// Inverse of `encode_len`.
+fn decode_len(len_buf: &[u8], endianness: Endianness) -> u64 {
+  return match endianness {
+    Endianness::Big => len_buf.iter().fold(0, |len, &b| (len << 8) ^ (b as u64)),
+    Endianness::Little => len_buf.iter().rev().fold(0, |len, &b| (len << 8) ^ (b as u64)),
+  };
+}
+
+/**
+ * Undoes COBS in place (the output is never longer than the input).  `buf` excludes the delimiter.
+ * Returns the decoded length, or None if the codes don't line up with the data.
+ */
+fn cobs_decode_in_place(buf: &mut [u8]) -> Option<usize> {
+  let mut r = 0;
+  let mut w = 0;
+  while r < buf.len() {
+    let code = buf[r] as usize;
+    if code == 0 || r + code > buf.len() {
+      return None;
+    }
+    buf.copy_within(r + 1..r + code, w);
+    w += code - 1;
+    r += code;
+    if code < 0xFF && r < buf.len() {
+      buf[w] = 0;
+      w += 1;
+    }
+  }
+  return Some(w);
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SINK: FrameSink, CK: Checksum> EncoderT for Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SINK, CK> {
+  type Error = SINK::Error;
+
   /*
   //THINK It might be nice if we could figure out a way to pass back data without first having to know how much we need
   //THINK Should we also incorporate the "offset" thing somehow?
@@ -109,122 +863,133 @@ impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, STATE> Encoder
   */
 
   //RAINY It's kinda weird passing baud and delay in; maybe bundle them up or pass them in another way.  BAUD's a const, in main....
-  fn write(&mut self, msg: &[u8]) -> Result<(), ()> { //THINK Should probably have proper ok/err types
+  /**
+   * The sink may return Partial(n) (n = total bytes taken, across all the buffers) or Err(WouldBlock);
+   * we remember how far into the frame we got and return Err(WouldBlock).  Call `write` again
+   * with the same `msg` and we pick up where the sink left off.
+   * The sink's `before_tx` is called at the start of a frame, and `after_tx` once the frame is fully out (or failed).
+   */
+  fn write(&mut self, msg: &[u8]) -> Result<(), nb::Error<DencoderError<SINK::Error>>> {
+    return self.write_vectored(&[msg]);
+  }
+
+  /**
+   * As `write`.  The parts go to the sink as they are, and the checksum's worked out across them,
+   * so nothing's copied.
+   */
+  fn write_vectored(&mut self, parts: &[&[u8]]) -> Result<(), nb::Error<DencoderError<SINK::Error>>> {
     trace!("-->den.write");
-    //DUMMY Error correction, retransmission
-    //CHECK Little or big endian?  Optionize?
-    //RAINY Make non-blocking?
-    if msg.len() >= (1 << (8*LEN_PREFIX_BYTES)) {
-      trace!("<--den.write");
-      return Err(());
-    }
+    let msg_len: usize = parts.iter().map(|p| p.len()).sum();
+    let head = match self.frame_head(msg_len) {
+      Some(head) => head,
+      None => {
+        trace!("<--den.write");
+        self.stats.oversize_drops += 1;
+        return Err(nb::Error::Other(DencoderError::MessageTooLong));
+      },
+    };
 
-    let mut len_buf: [u8; LEN_PREFIX_BYTES] = [0; LEN_PREFIX_BYTES];
-    let mut l = msg.len(); // Does not include the checksum
-    for i in (0..LEN_PREFIX_BYTES).rev() { //CHECK Is this notably slow?  I'd hope not.
-      len_buf[i] = l as u8;
-      l = l >> 8;
+    let mut msg_ck = CK::default(); //CHECK Should I hash the msg, or the entire preceding packet?
+    for p in parts {
+      msg_ck.update(p);
     }
-
-    let len_hash = Sha256::digest(len_buf);
-    let len_checksum = &len_hash[0..LEN_PREFIX_BYTES];
-
-    let msg_hash = Sha256::digest(msg); //CHECK Should I hash the msg, or the entire preceding packet?
+    let msg_hash = msg_ck.finalize();
     let msg_checksum = &msg_hash[0..CHECKSUM_BYTES];
 
-    match &mut self.before_tx {
-      Some(f) => f(&mut self.state),
-      None => (),
-    };
-    match (self.tx)(&mut self.state, &[
-      &[MAGIC_BYTE],
-      &len_buf,
-      &len_checksum,
-      msg,
-      &msg_checksum,
-    ]) {
-        Ok(TransmissionStatus::Complete) => (),
-        Ok(TransmissionStatus::Partial(_n)) => error!("partial tx not yet handled"),
-        Err(_) => error!("tx error, not handled"),
+    if self.progress == 0 {
+      self.sink.before_tx();
+    }
+
+    let mut offset = 0;
+    let fec_parity = self.fec_parity;
+    let sent = match self.framing {
+      Framing::MagicByte => Self::emit_frame(head.pieces(), parts, msg_checksum, fec_parity, |p| self.send(&mut offset, p)),
+      Framing::Cobs => {
+        let mut run = CobsRun { bytes: [0; 254], len: 0 };
+        Self::emit_frame(head.pieces(), parts, msg_checksum, fec_parity, |p| self.send_cobs(&mut offset, &mut run, p))
+          .and_then(|()| self.finish_cobs(&mut offset, &mut run))
+      },
     };
-    match &mut self.after_tx {
-      Some(f) => f(&mut self.state),
-      None => (),
+    let tx_error = match sent {
+        Ok(()) => None,
+        Err(nb::Error::WouldBlock) => {
+          trace!("<--den.write partial {}", self.progress);
+          return Err(nb::Error::WouldBlock);
+        },
+        Err(nb::Error::Other(e)) => Some(e),
     };
-    let h = to_hex_string(&[MAGIC_BYTE]);
-    trace!("den.write wrote {}", h.as_str());
-    let h = to_hex_string(&len_buf);
-    trace!("den.write wrote {}", h.as_str());
-    let h = to_hex_string(&len_checksum);
-    trace!("den.write wrote {}", h.as_str());
-    let h = to_hex_string(msg);
-    trace!("den.write wrote {}", h.as_str());
-    let h = to_hex_string(&msg_checksum);
+    self.progress = 0;
+    self.sink.after_tx();
+    if let Some(e) = tx_error {
+      trace!("<--den.write");
+      self.stats.transport_errors += 1;
+      return Err(nb::Error::Other(DencoderError::Transport(e)));
+    }
+    self.count_frame();
+    for p in head.pieces() {
+      let h = to_hex_string(p);
+      trace!("den.write wrote {}", h.as_str());
+    }
+    for p in parts {
+      let h = to_hex_string(p);
+      trace!("den.write wrote {}", h.as_str());
+    }
+    let h = to_hex_string(msg_checksum);
     trace!("den.write wrote {}", h.as_str());
     trace!("<--den.write");
     return Ok(());
   }
 }
 
-/// See Encoder
-impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, STATE, const BUF_SIZE: usize> Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, STATE, BUF_SIZE> {
-  pub fn new(state: STATE, before_rx: Option<fn(&mut STATE)>, rx: fn(&mut STATE, &mut [u8]) -> Result<TransmissionStatus, nb::Error<()>>, after_rx: Option<fn(&mut STATE)>) -> Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, STATE, BUF_SIZE> {
+/// See Encoder.  SOURCE is where the bytes come from; see `transport`.
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SOURCE: FrameSource, const BUF_SIZE: usize, CK: Checksum> Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SOURCE, BUF_SIZE, CK> {
+  pub fn new(source: SOURCE) -> Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SOURCE, BUF_SIZE, CK> {
     return Decoder {
-      state: state,
+      source: source,
       incoming_message: Vec::new(),
-      before_rx: before_rx,
-      rx: rx,
-      after_rx: after_rx,
+      phase: RxPhase::Magic,
+      header_bytes: 0,
+      framing: Framing::MagicByte,
+      endianness: Endianness::Big,
+      fec_parity: 0,
+      stats: DecoderStats::default(),
+      rate_meter: None,
+      checksum: PhantomData,
     };
   }
 
-  /// STATE is input not yet processed
+  pub fn source(&self) -> &SOURCE {
+    return &self.source;
+  }
+
+  pub fn source_mut(&mut self) -> &mut SOURCE {
+    return &mut self.source;
+  }
+
+  //RAINY Should this be on the trait?
   /**
-   * Returns a Decoder that you can .add() data to.
-   * //RAINY Currently you have to manually specify the state for Decoder, sorry.  Depending on the side you're specifying it on, either Vec<u8, BUF_SIZE>, or about anything at all, will do.
+   * Clears `incoming_message`, resetting the built-in internal state of Decoder.
+   * Notably, does not touch the source, which the built-in code knows nothing about.
    */
-  pub fn new_plain() -> Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, Vec<u8, BUF_SIZE>, BUF_SIZE> {
-    let d = Decoder {
-      state: Vec::<u8, BUF_SIZE>::new(),
-      incoming_message: Vec::new(),
-      before_rx: None,
-      // Note: Decoder calls rx repeatedly with a buffer of the size it wants.
-      rx: |state, buffer| -> Result<TransmissionStatus, nb::Error<()>> { // Rx
-          if state.len() < buffer.len() {
-            // We have less data than we want
-            let n = state.len();
-            if n == 0 {
-              return Err(nb::Error::WouldBlock);
-            }
-            buffer[0..n].copy_from_slice(state);
-            state.clear();
-            return Ok(TransmissionStatus::Partial(n));
-          } else if state.len() > buffer.len() {
-            // We have more data than we want
-            let n = buffer.len();
-            buffer.copy_from_slice(state);
-            state.drain(0..n); //LEAK I wonder if there's a more efficient way?
-            return Ok(TransmissionStatus::Complete);
-          } else {
-            // We have exactly as much data as we want
-            let n = state.len();
-            buffer[0..n].copy_from_slice(state);
-            state.clear();
-            return Ok(TransmissionStatus::Complete);
-          }
-        },
-      after_rx: None,
-    };
-    return d;
+  pub fn clear(&mut self) {
+    self.drop_frame();
   }
 }
 
-impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, const BUF_SIZE: usize> Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, Vec<u8,BUF_SIZE>, BUF_SIZE> {
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, const BUF_SIZE: usize, CK: Checksum> Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, BufferSource<BUF_SIZE>, BUF_SIZE, CK> {
+  /**
+   * Returns a Decoder that you can .add() data to.
+   * BUF_SIZE doubles as the size of the pending input buffer.
+   */
+  pub fn new_plain() -> Self {
+    return Decoder::new(BufferSource::new());
+  }
+
   /**
-   * Copies `input` onto the pending buffer in `state`.  Returns error if out of space.
+   * Copies `input` onto the pending input buffer.  Returns error if out of space.
    */
   pub fn add(&mut self, input: &[u8]) -> Result<(), CapacityError> {
-    return self.state.extend_from_slice(input);
+    return self.source.add(input);
   }
 
   // /**
@@ -249,177 +1014,547 @@ impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, const BUF_SIZE
    * Removes current input from buffer (clearing the buffer) and returns it.
    */
   pub fn recover_input(&mut self) -> Vec<u8,BUF_SIZE> {
-    return core::mem::replace(&mut self.state, Vec::new());
+    return self.source.take();
   }
+}
 
-  //RAINY Should this be on the trait?
-  //RAINY Should we provide a way to reset STATE?
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SOURCE: FrameSource, const BUF_SIZE: usize, CK: Checksum> Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SOURCE, BUF_SIZE, CK> {
   /**
-   * Clears `incoming_message`, resetting the built-in internal state of Decoder.
-   * Notably, does not reset the STATE field, which the built-in code knows nothing about.
+   * Sets how frames are delimited; must match the Encoder.  Drops any frame in progress.
    */
-  pub fn clear(&mut self) {
+  pub fn with_framing(mut self, framing: Framing) -> Self {
+    self.framing = framing;
+    self.drop_frame();
+    return self;
+  }
+
+  /**
+   * Sets the byte order of the length prefix; must match the Encoder.
+   */
+  pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+    self.endianness = endianness;
+    return self;
+  }
+
+  /**
+   * Expects Reed-Solomon parity able to repair `correctable` bytes per block; must match the
+   * Encoder's `with_fec`.  0 turns it off.  Remember BUF_SIZE has to fit the parity too.
+   * Panics if `correctable` is more than `fec::MAX_CORRECTABLE`.
+   */
+  pub fn with_fec(mut self, correctable: usize) -> Self {
+    assert!(correctable <= fec::MAX_CORRECTABLE, "with_fec: at most {} correctable bytes", fec::MAX_CORRECTABLE);
+    self.fec_parity = 2 * correctable;
+    self.drop_frame();
+    return self;
+  }
+
+  /// Counts of frames FEC repaired or couldn't, since the Decoder was made.  Also in `stats`.
+  pub fn fec_stats(&self) -> FecStats {
+    return self.stats.fec;
+  }
+
+  /**
+   * Counts each message delivered on `meter` too; call its `check` or `measure` (via `rate_meter_mut`) for frames per second.
+   */
+  pub fn with_rate_meter(mut self, meter: RateMeter) -> Self {
+    self.rate_meter = Some(meter);
+    return self;
+  }
+
+  pub fn rate_meter_mut(&mut self) -> Option<&mut RateMeter> {
+    return self.rate_meter.as_mut();
+  }
+
+  /**
+   * Counts of messages and bytes received, and of frames dropped and why, since the Decoder was made.
+   * Resyncs, skipped bytes and bytes only come from `read`; `decode_in_place` has nothing to say about
+   * bytes it was handed.
+   */
+  pub fn stats(&self) -> DecoderStats {
+    return self.stats;
+  }
+
+  /**
+   * Reads from the source straight into `incoming_message` until it holds `needed` bytes.
+   * On a short read, keeps what we got and returns WouldBlock.
+   */
+  fn fill(&mut self, needed: usize) -> Result<(), nb::Error<DencoderError<SOURCE::Error>>> {
+    if self.framing == Framing::Cobs && self.phase != RxPhase::Delimiter {
+      // We already have the whole frame, and it came up short
+      self.drop_frame();
+      return Err(nb::Error::Other(DencoderError::BadFraming));
+    }
+    let have = self.incoming_message.len();
+    if self.incoming_message.resize_default(needed).is_err() {
+      self.drop_frame();
+      return Err(nb::Error::Other(DencoderError::BufferFull));
+    }
+    match self.source.rx(&mut self.incoming_message[have..needed]) {
+      Ok(TransmissionStatus::Complete) => {
+        self.stats.bytes += (needed - have) as u64;
+        return Ok(());
+      },
+      Ok(TransmissionStatus::Partial(n)) => {
+        self.stats.bytes += n as u64;
+        self.incoming_message.truncate(have + n);
+        return Err(nb::Error::WouldBlock);
+      },
+      Err(nb::Error::WouldBlock) => {
+        self.incoming_message.truncate(have);
+        return Err(nb::Error::WouldBlock);
+      },
+      Err(nb::Error::Other(e)) => {
+        self.incoming_message.truncate(have);
+        return Err(nb::Error::Other(DencoderError::Transport(e)));
+      },
+    };
+  }
+
+  /**
+   * Drops the frame in progress and goes back to looking for a magic byte.
+   */
+  fn drop_frame(&mut self) {
     self.incoming_message.clear();
+    self.phase = match self.framing {
+      Framing::MagicByte => RxPhase::Magic,
+      Framing::Cobs => RxPhase::Delimiter,
+    };
   }
-}
 
-impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, STATE, const BUF_SIZE: usize> DecoderT for Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, STATE, BUF_SIZE> {
-  // Returns error if error, else overwrites buffer with received message and sets buffer.length accordingly
-  //THINK If a message fails validation, should I return an error?
-  //        I think the eventual goal is that we shall handle all such problems
-  fn read<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>) -> Result<(), nb::Error<()>> { //THINK Should it return Err(size of waiting message) if too big, or st?
-    trace!("-->den.read");
-    match &mut self.before_rx {
-      Some(f) => f(&mut self.state),
-      None => (),
+  /**
+   * Called when a frame fails validation.  Its magic byte may have been a false positive (e.g. a
+   * 0xA9 in some payload), in which case the real frame start is somewhere in the bytes we already
+   * read.  So, drop just the magic byte and rescan the rest from the next magic byte on.
+   * With COBS framing there's no doubt where frames start, so the whole frame just goes.
+   */
+  fn resync(&mut self) {
+    if self.framing == Framing::Cobs {
+      self.drop_frame();
+      return;
+    }
+    if self.phase != RxPhase::Magic {
+      self.stats.resyncs += 1;
+    }
+    let skipped = match self.incoming_message[1..].iter().position(|&b| b == MAGIC_BYTE || b == MAGIC_BYTE_HEADERED) {
+      Some(i) => i + 1,
+      None => self.incoming_message.len(),
     };
+    self.incoming_message.drain(0..skipped); //LEAK Shifts the buffer down; only happens on bad frames, though
+    self.stats.skipped_bytes += skipped as u64;
+    self.phase = RxPhase::Magic;
+  }
+}
 
-    /*
-    So, this goes through the steps and compares against how much data we already have, to figure out where in the process we are,
-    and resume from there, returning Err(WouldBlock) when would block.
-    //LEAK I imagine this wastes time?  Can we keep state better?
-     */
+/// Why `Decoder::check_header` didn't accept a header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum HeaderError {
+  /// Needs this many bytes of the frame to tell.
+  More(usize),
+  /// Not a header at all; the magic byte was noise.
+  FalseStart,
+  /// A genuine frame from a sender with other parameters.
+  Mismatch(FrameFormat),
+}
 
-    // Looping so I can restart on validation failure
-    'readloop: loop {
-      let mut benchmark = 0; // For tracking target index
-
-      // Find magic byte
-      benchmark += 1;
-      if self.incoming_message.len() < benchmark {
-        //CHECK I don't really like using magic bytes, I think I'd prefer to just check all alignments and rely on checksums
-        let mut one_byte = [0u8; 1];
-        while MAGIC_BYTE != (match (self.rx)(&mut self.state, &mut one_byte) {
-          Ok(TransmissionStatus::Complete) => {
-            one_byte[0]
-          },
-          Ok(TransmissionStatus::Partial(n)) => {
-            error!("Weird; got Partial({}) for [u8; 1]?", n);
-            return Err(nb::Error::WouldBlock);
-          },
-          Err(e) => return Err(e),
-        }) {};
-        if self.incoming_message.push(MAGIC_BYTE).is_err() {
-          return Err(nb::Error::Other(())); //RAINY Specify an error
+/// Why `Decoder::decode_in_place` has no message for you.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InPlaceError {
+  /// WouldBlock if there's no whole frame yet; otherwise why the frame was bad.
+  pub error: nb::Error<DencoderError<Infallible>>,
+  /// How many bytes at the front of the buffer can be dropped before trying again: garbage, or the
+  /// bad frame (or with `Framing::MagicByte`, just its magic byte, since that may have been a false start).
+  pub consumed: usize,
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SOURCE, const BUF_SIZE: usize, CK: Checksum> Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SOURCE, BUF_SIZE, CK> {
+  /**
+   * Counts the outcome of a `read` or `decode_in_place`.
+   */
+  fn count_result<E>(&mut self, r: Result<(), &nb::Error<DencoderError<E>>>) {
+    match r {
+      Ok(()) => {
+        self.stats.frames += 1;
+        if let Some(meter) = self.rate_meter.as_mut() {
+          meter.inc();
         }
-        // Found magic byte
-      }
+      },
+      Err(nb::Error::Other(e)) => self.stats.count_failure(e),
+      Err(nb::Error::WouldBlock) => (),
+    };
+  }
 
-      let mut load_bytes = |incoming_message: &mut Vec<u8, BUF_SIZE>, mut buf: &mut [u8]| -> Result<_, nb::Error<()>> { //THINK Might be clearer to do `count` instead of buf; not really necessary I think.  Can we do const params?
-        match (self.rx)(&mut self.state, &mut buf) {
-          Ok(TransmissionStatus::Complete) => {
-            for &mut b in buf {
-              if incoming_message.push(b).is_err() {
-                return Err(nb::Error::Other(())); //RAINY Specify an error
-              }
-            }
-          },
-          Ok(TransmissionStatus::Partial(n)) => {
-            for &b in buf[..n].iter() {
-              if incoming_message.push(b).is_err() {
-                return Err(nb::Error::Other(())); //RAINY Specify an error
-              }
-            }
-            return Err(nb::Error::WouldBlock);
-          },
-          Err(e) => return Err(e),
-        };
-        return Ok(TransmissionStatus::Complete);
-      };
+  /**
+   * Checks the header of `head` (a frame from its magic byte on, as much of it as has arrived) against
+   * our own parameters.  The header is only covered by the checksums, which a sender built differently
+   * works out its own way; so a header that isn't ours is only believed once the frame's checksums
+   * check out as the header says the sender computed them, FOREIGN_CHECKED_BYTES of them at least.
+   * Otherwise (or if it can't be checked, e.g. a checksum we don't have) the magic byte was just noise.
+   */
+  fn check_header(&self, head: &[u8]) -> Result<(), HeaderError> {
+    if head.len() < 1 + FRAME_HEADER_BYTES {
+      return Err(HeaderError::More(1 + FRAME_HEADER_BYTES));
+    }
+    let header = [head[1], head[2]];
+    let expected = FrameFormat::new(LEN_PREFIX_BYTES, CHECKSUM_BYTES, CK::ID, self.endianness, self.fec_parity > 0).to_bytes();
+    if header == expected {
+      return Ok(());
+    }
+    let theirs = FrameFormat::from_bytes(header);
+    let digest = |data: &[u8]| if theirs.checksum_id == CK::ID { Some(CK::digest(data)) } else { checksum::digest_by_id(theirs.checksum_id, data) };
+    if theirs.version != FORMAT_VERSION || theirs.checksum_bytes > MAX_CHECKSUM_BYTES || digest(&[]).is_none() {
+      return Err(HeaderError::FalseStart);
+    }
+
+    let l = theirs.len_prefix_bytes;
+    let len_checksum_start = 1 + FRAME_HEADER_BYTES + l;
+    let body_start = len_checksum_start + l;
+    if head.len() < body_start {
+      return Err(HeaderError::More(body_start));
+    }
+    let len_hash = digest(&head[1..len_checksum_start]).unwrap_or_default(); // Header and length
+    if len_hash[..l] != head[len_checksum_start..body_start] {
+      return Err(HeaderError::FalseStart);
+    }
 
-      // Read length
-      benchmark += LEN_PREFIX_BYTES;
-      if self.incoming_message.len() < benchmark {
-        let missing: usize = benchmark - self.incoming_message.len();
-        let mut buf: [u8;LEN_PREFIX_BYTES] = [0;LEN_PREFIX_BYTES]; // This buffer probably isn't necessary, buuuut....
-        load_bytes(&mut self.incoming_message, &mut buf[..missing])?;
+    // The length checksum alone may be too short to go on; then the message checksum has to agree too
+    if l < FOREIGN_CHECKED_BYTES {
+      // (Without knowing how much FEC parity there is, there's no finding the checksum)
+      if theirs.fec || l + theirs.checksum_bytes < FOREIGN_CHECKED_BYTES {
+        return Err(HeaderError::FalseStart);
       }
-      let mut len_buf = [0_u8;LEN_PREFIX_BYTES]; //LEAK It would be nicer if we could skip the initialization step
-      len_buf.copy_from_slice(&self.incoming_message[(benchmark-LEN_PREFIX_BYTES)..benchmark]);
-
-      // Read length checksum
-      benchmark += LEN_PREFIX_BYTES;
-      if self.incoming_message.len() < benchmark {
-        let missing: usize = benchmark - self.incoming_message.len();
-        //THINK Note the length checksum is LEN_PREFIX_BYTES long, not CHECKSUM_BYTES long; kinda confusing
-        let mut buf: [u8;LEN_PREFIX_BYTES] = [0;LEN_PREFIX_BYTES]; // Ditto
-        load_bytes(&mut self.incoming_message, &mut buf[..missing])?;
-        let len_checksum = &self.incoming_message[(benchmark-LEN_PREFIX_BYTES)..benchmark];
-
-        // Check length checksum
-        let len_hash = Sha256::digest(len_buf);
-        let len_checksum_calc = &len_hash[0..LEN_PREFIX_BYTES]; //DITTO Confusing name
-        for i in 0..LEN_PREFIX_BYTES {
-          if len_checksum[i] != len_checksum_calc[i] {
-            let a = to_hex_string(&len_checksum);
-            let b = to_hex_string(len_checksum_calc);
-            error!("den.read: Incoming message failed length checksum {} != {}", a.as_str(), b.as_str());
-            self.incoming_message.clear();
-            continue 'readloop;
-          }
-        }
+      let len = match usize::try_from(decode_len(&head[len_checksum_start - l..len_checksum_start], theirs.endianness)) {
+        Ok(len) if len <= usize::MAX - body_start - theirs.checksum_bytes => len,
+        _ => return Err(HeaderError::FalseStart),
+      };
+      let frame_len = body_start + len + theirs.checksum_bytes;
+      if head.len() < frame_len {
+        return Err(HeaderError::More(frame_len));
       }
-      
-      // Passed length checksum; verify we have enough space
-      let mut len: usize = 0;
-      for b in len_buf {
-        len = (len << 8) ^ (b as usize);
+      let msg_hash = digest(&head[body_start..body_start + len]).unwrap_or_default();
+      if msg_hash[..theirs.checksum_bytes] != head[body_start + len..frame_len] {
+        return Err(HeaderError::FalseStart);
       }
-      match buffer.resize_default(len) {
-        Ok(_) => {
-          // Pass through
-        },
-        Err(_) => {
-          error!("den.read: Incoming message too big(?) {} > {}, dropped", len, CAPACITY); //DUMMY I think this encountered an example of like, the magic byte was in the message, and it got off track, and failed to recover, always parsing the msg wrong
-          self.incoming_message.clear();
-          continue 'readloop;
+    }
+    error!("den.read: Incoming frame has format {:?}, expected {:?}", theirs, FrameFormat::from_bytes(expected));
+    return Err(HeaderError::Mismatch(theirs));
+  }
+
+  /**
+   * Checks the length checksum of `head` (magic byte through length checksum), and returns the length.
+   */
+  fn check_length(&self, head: &[u8]) -> Result<u64, DencoderError<Infallible>> {
+    let len_checksum_start = head.len() - LEN_PREFIX_BYTES;
+    let len_buf = &head[len_checksum_start - LEN_PREFIX_BYTES..len_checksum_start];
+    //THINK Note the length checksum is LEN_PREFIX_BYTES long, not CHECKSUM_BYTES long; kinda confusing
+    let len_checksum = &head[len_checksum_start..];
+    let len_hash = CK::digest(&head[1..len_checksum_start]); // Header and length
+    let len_checksum_calc = &len_hash[0..LEN_PREFIX_BYTES]; //DITTO Confusing name
+    if len_checksum != len_checksum_calc {
+      let a = to_hex_string(len_checksum);
+      let b = to_hex_string(len_checksum_calc);
+      error!("den.read: Incoming message failed length checksum {} != {}", a.as_str(), b.as_str());
+      return Err(DencoderError::LengthChecksum);
+    }
+    return Ok(decode_len(len_buf, self.endianness));
+  }
+
+  /**
+   * Repairs `body` (the message and checksum of a `len`-byte message, plus any FEC parity) in
+   * place, and checks the message checksum.  On success the message is `body[..len]`.
+   */
+  fn check_body(body: &mut [u8], len: usize, fec_parity: usize, fec_stats: &mut FecStats) -> Result<(), DencoderError<Infallible>> {
+    let mut corrected = 0;
+    if fec_parity > 0 {
+      // Repairs in place, and packs message and checksum together like a frame without FEC
+      match fec::correct_blocks(body, fec_parity) {
+        Some(n) => corrected = n,
+        None => {
+          error!("den.read: Incoming message beyond FEC repair");
+          fec_stats.uncorrectable_frames += 1;
+          return Err(DencoderError::Uncorrectable);
         },
       };
-      
-      // Passed length verification; read message
-      benchmark += len;
-      if self.incoming_message.len() < benchmark {
-        let missing: usize = benchmark - self.incoming_message.len();
-        let mut buf: [u8;CAPACITY] = [0;CAPACITY]; // Ditto
-        load_bytes(&mut self.incoming_message, &mut buf[..missing])?;
-      }
-      let mut msg_buf0 = [0_u8;CAPACITY]; //LEAK //DITTO Esp here
-      let msg_buf = &mut msg_buf0[0..len];
-      msg_buf.copy_from_slice(&self.incoming_message[(benchmark-len)..benchmark]);
-
-      // Read msg checksum
-      benchmark += CHECKSUM_BYTES;
-      if self.incoming_message.len() < benchmark {
-        let missing: usize = benchmark - self.incoming_message.len();
-        let mut buf: [u8;CHECKSUM_BYTES] = [0;CHECKSUM_BYTES]; // Ditto
-        load_bytes(&mut self.incoming_message, &mut buf[..missing])?;
+    }
+    let msg = &body[..len];
+    let msg_checksum = &body[len..len + CHECKSUM_BYTES];
+    let msg_hash = CK::digest(msg);
+    let msg_checksum_calc = &msg_hash[0..CHECKSUM_BYTES];
+    if msg_checksum != msg_checksum_calc {
+      let a = to_hex_string(msg_checksum);
+      let b = to_hex_string(msg_checksum_calc);
+      error!("den.read: Incoming message failed msg checksum {} != {}", a.as_str(), b.as_str());
+      if corrected > 0 {
+        fec_stats.uncorrectable_frames += 1;
       }
-      let mut msg_checksum = [0_u8;CHECKSUM_BYTES]; //LEAK It would be nicer if we could skip the initialization step
-      msg_checksum.copy_from_slice(&self.incoming_message[(benchmark-CHECKSUM_BYTES)..benchmark]);
-
-      // Check message checksum
-      let msg_hash = Sha256::digest(&msg_buf);
-      let msg_checksum_calc = &msg_hash[0..CHECKSUM_BYTES];
-      for i in 0..CHECKSUM_BYTES {
-        if msg_checksum[i] != msg_checksum_calc[i] {
-          let a = to_hex_string(&msg_checksum);
-          let b = to_hex_string(msg_checksum_calc);
-          error!("den.read: Incoming message failed msg checksum {} != {}", a.as_str(), b.as_str());
-          //THINK Error correction?
-          self.incoming_message.clear();
-          continue 'readloop;
+      return Err(DencoderError::MessageChecksum);
+    }
+    if corrected > 0 {
+      trace!("den.read: FEC repaired {} bytes", corrected);
+      fec_stats.corrected_frames += 1;
+      fec_stats.corrected_bytes += corrected as u32;
+    }
+    return Ok(());
+  }
+
+  /**
+   * Finds and checks the first frame in `buf`, entirely in place, and returns the message (borrowed
+   * from `buf`) and how many bytes of `buf` the frame took up, through its end.  Nothing's copied
+   * and nothing's kept between calls, so this is for when the bytes are already sitting in a buffer
+   * of your own, e.g. filled by DMA; the source and BUF_SIZE don't come into it
+   * (`Decoder::<L, C, _, 0>::new_plain()` will do).  Drop the consumed bytes and call again for the
+   * next frame.
+   * `buf` is modified: COBS is undone and FEC repairs are made in place, and the message checksum
+   * lands right after the message.  So only call again on bytes after `consumed`.
+   * Returns WouldBlock (see `InPlaceError`) if `buf` doesn't hold a whole frame yet.  Make sure
+   * it can hold the biggest frame you expect, or a long enough frame will never finish.
+   */
+  pub fn decode_in_place<'b>(&mut self, buf: &'b mut [u8]) -> Result<(&'b [u8], usize), InPlaceError> {
+    let r = self.find_in_place(buf);
+    self.count_result(r.as_ref().map(|_| ()).map_err(|e| &e.error));
+    return r;
+  }
+
+  fn find_in_place<'b>(&mut self, buf: &'b mut [u8]) -> Result<(&'b [u8], usize), InPlaceError> {
+    let (start, end, frame_end) = match self.framing {
+      Framing::MagicByte => {
+        let mut from = 0;
+        let start = loop {
+          let start = match buf[from..].iter().position(|&b| b == MAGIC_BYTE || b == MAGIC_BYTE_HEADERED) {
+            Some(i) => from + i,
+            None => return Err(InPlaceError { error: nb::Error::WouldBlock, consumed: buf.len() }),
+          };
+          // A 0xA5 whose header doesn't check out is just noise; look on past it
+          if buf[start] == MAGIC_BYTE_HEADERED && self.check_header(&buf[start..]) == Err(HeaderError::FalseStart) {
+            from = start + 1;
+            continue;
+          }
+          break start;
+        };
+        (start, buf.len(), None)
+      },
+      Framing::Cobs => {
+        // Empty frames (back-to-back delimiters) are harmless; skip them
+        let start = buf.iter().position(|&b| b != COBS_DELIMITER).unwrap_or(buf.len());
+        let delimiter = match buf[start..].iter().position(|&b| b == COBS_DELIMITER) {
+          Some(i) => start + i,
+          None => return Err(InPlaceError { error: nb::Error::WouldBlock, consumed: start }),
+        };
+        match cobs_decode_in_place(&mut buf[start..delimiter]) {
+          Some(decoded) => (start, start + decoded, Some(delimiter + 1)),
+          None => {
+            error!("den.read: Incoming COBS frame failed to decode");
+            return Err(InPlaceError { error: nb::Error::Other(DencoderError::BadFraming), consumed: delimiter + 1 });
+          },
         }
-      }
+      },
+    };
+    // With MagicByte, a bad frame only costs its magic byte; with COBS, everything up to the delimiter
+    let bad_consumed = frame_end.unwrap_or(start + 1);
+    let bad = |e| Err(InPlaceError { error: nb::Error::Other(e), consumed: bad_consumed });
+    let more = Err(InPlaceError { error: nb::Error::WouldBlock, consumed: start });
+    let frame = &mut buf[start..end];
 
-      match &mut self.after_rx {
-        Some(f) => f(&mut self.state),
-        None => (),
+    let header_bytes = if frame.first() == Some(&MAGIC_BYTE_HEADERED) { FRAME_HEADER_BYTES } else { 0 };
+    if frame.first() != Some(&MAGIC_BYTE) && header_bytes == 0 {
+      error!("den.read: Incoming COBS frame missing magic byte");
+      return bad(DencoderError::BadFraming);
+    }
+    if header_bytes > 0 {
+      match self.check_header(frame) {
+        Ok(()) => (),
+        Err(HeaderError::More(_)) => return if frame_end.is_some() { bad(DencoderError::BadFraming) } else { more },
+        Err(HeaderError::FalseStart) => return bad(DencoderError::BadFraming), // Only with COBS; see above
+        Err(HeaderError::Mismatch(theirs)) => return bad(DencoderError::FormatMismatch(theirs)),
       };
+    }
+    let body_start = 1 + header_bytes + 2 * LEN_PREFIX_BYTES;
+    if frame.len() < body_start {
+      return if frame_end.is_some() { bad(DencoderError::BadFraming) } else { more };
+    }
+    let len = match self.check_length(&frame[..body_start]) {
+      Ok(len) => len,
+      Err(e) => return bad(e),
+    };
+    // A length that can't even be addressed can't be a frame in a buffer we were handed
+    let body_len = match usize::try_from(len) {
+      Ok(len) if len <= frame.len() => fec::encoded_len(len + CHECKSUM_BYTES, self.fec_parity),
+      _ if frame_end.is_some() => return bad(DencoderError::BadFraming),
+      _ => return more,
+    };
+    let len = len as usize;
+    if frame.len() < body_start + body_len || (frame_end.is_some() && frame.len() != body_start + body_len) {
+      return if frame_end.is_some() { bad(DencoderError::BadFraming) } else { more };
+    }
+    if let Err(e) = Self::check_body(&mut frame[body_start..body_start + body_len], len, self.fec_parity, &mut self.stats.fec) {
+      return bad(e);
+    }
+    let consumed = frame_end.unwrap_or(start + body_start + body_len);
+    return Ok((&buf[start + body_start..start + body_start + len], consumed));
+  }
+}
 
-      buffer.copy_from_slice(msg_buf); // `buffer` was already resized to len, as is msg_buf
-      self.incoming_message.clear();
-      trace!("<--den.read");
-      return Ok(());
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SOURCE: FrameSource, const BUF_SIZE: usize, CK: Checksum> DecoderT for Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SOURCE, BUF_SIZE, CK> {
+  type Error = SOURCE::Error;
+
+  // Returns error if error, else overwrites buffer with received message and sets buffer.length accordingly
+  // A frame that fails validation is dropped and reported as an error; call `read` again to carry on with the next one.
+  fn read<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>) -> Result<(), nb::Error<DencoderError<SOURCE::Error>>> {
+    let r = self.read_frame(buffer);
+    self.count_result(r.as_ref().map(|_| ()));
+    return r;
+  }
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SOURCE: FrameSource, const BUF_SIZE: usize, CK: Checksum> Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SOURCE, BUF_SIZE, CK> {
+  fn read_frame<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>) -> Result<(), nb::Error<DencoderError<SOURCE::Error>>> {
+    trace!("-->den.read");
+    self.source.before_rx();
+
+    /*
+    Each phase knows where its bytes live in `incoming_message`; we read exactly up to the end of the
+    current phase, check whatever there is to check, and move on.  Bytes are read straight into
+    `incoming_message` and never moved until the message is copied out at the end.
+    If a frame fails validation, `resync` keeps the bytes after its magic byte, and the phases
+    below consume those before asking `rx` for more.
+     */
+    loop {
+      let len_start = 1 + self.header_bytes;
+      let len_checksum_start = len_start + LEN_PREFIX_BYTES;
+      let body_start = len_checksum_start + LEN_PREFIX_BYTES;
+      let needed = match self.phase {
+        RxPhase::Delimiter => self.incoming_message.len() + 1,
+        RxPhase::Magic => 1,
+        RxPhase::Header => len_start,
+        RxPhase::ForeignHeader { head_len } => head_len,
+        RxPhase::Length => len_checksum_start,
+        RxPhase::LengthChecksum => body_start,
+        RxPhase::Body { len } => body_start + len,
+        RxPhase::Checksum { len } => body_start + fec::encoded_len(len + CHECKSUM_BYTES, self.fec_parity),
+      };
+      if self.incoming_message.len() < needed && let Err(e) = self.fill(needed) {
+        trace!("<--den.read");
+        return Err(e);
+      }
+
+      match self.phase {
+        RxPhase::Delimiter => {
+          let n = self.incoming_message.len() - 1;
+          if self.incoming_message[n] == COBS_DELIMITER {
+            if n == 0 {
+              // Empty frame; harmless, just skip it
+              self.incoming_message.clear();
+              continue;
+            }
+            match cobs_decode_in_place(&mut self.incoming_message[..n]) {
+              Some(decoded) => {
+                self.incoming_message.truncate(decoded);
+                self.phase = RxPhase::Magic;
+              },
+              None => {
+                error!("den.read: Incoming COBS frame failed to decode");
+                self.drop_frame();
+                return Err(nb::Error::Other(DencoderError::BadFraming));
+              },
+            };
+          }
+        },
+        RxPhase::Magic => {
+          //CHECK I don't really like using magic bytes, I think I'd prefer to just check all alignments and rely on checksums
+          if self.incoming_message[0] == MAGIC_BYTE {
+            self.header_bytes = 0;
+            self.phase = RxPhase::Length;
+          } else if self.incoming_message[0] == MAGIC_BYTE_HEADERED {
+            self.header_bytes = FRAME_HEADER_BYTES;
+            self.phase = RxPhase::Header;
+          } else if self.framing == Framing::Cobs {
+            error!("den.read: Incoming COBS frame missing magic byte");
+            self.drop_frame();
+            return Err(nb::Error::Other(DencoderError::BadFraming));
+          } else {
+            self.resync();
+          }
+        },
+        RxPhase::Header | RxPhase::ForeignHeader { .. } => {
+          match self.check_header(&self.incoming_message) {
+            Ok(()) => self.phase = RxPhase::Length,
+            // Can't wait for a frame that won't fit; it's most likely noise anyway
+            Err(HeaderError::More(head_len)) if head_len > BUF_SIZE && self.framing == Framing::MagicByte => self.resync(),
+            Err(HeaderError::More(head_len)) => self.phase = RxPhase::ForeignHeader { head_len },
+            Err(HeaderError::FalseStart) if self.framing == Framing::Cobs => {
+              error!("den.read: Incoming COBS frame has a bad header");
+              self.drop_frame();
+              return Err(nb::Error::Other(DencoderError::BadFraming));
+            },
+            Err(HeaderError::FalseStart) => self.resync(),
+            Err(HeaderError::Mismatch(theirs)) => {
+              self.resync();
+              return Err(nb::Error::Other(DencoderError::FormatMismatch(theirs)));
+            },
+          };
+        },
+        RxPhase::Length => {
+          self.phase = RxPhase::LengthChecksum;
+        },
+        RxPhase::LengthChecksum => {
+          let len = match self.check_length(&self.incoming_message[..body_start]) {
+            Ok(len) => len,
+            Err(e) => {
+              self.resync();
+              return Err(nb::Error::Other(e.cast()));
+            },
+          };
+
+          // Passed length checksum; verify we have enough space
+          if len > CAPACITY as u64 {
+            error!("den.read: Incoming message too big(?) {} > {}, dropped", len, CAPACITY);
+            self.resync();
+            return Err(nb::Error::Other(DencoderError::MessageTooBig));
+          }
+          let len = len as usize;
+          let frame_len = body_start + fec::encoded_len(len + CHECKSUM_BYTES, self.fec_parity);
+          if frame_len > BUF_SIZE {
+            error!("den.read: Incoming message too big for BUF_SIZE {} > {}, dropped", frame_len, BUF_SIZE);
+            self.resync();
+            return Err(nb::Error::Other(DencoderError::BufferFull));
+          }
+          self.phase = RxPhase::Body { len };
+        },
+        RxPhase::Body { len } => {
+          self.phase = RxPhase::Checksum { len };
+        },
+        RxPhase::Checksum { len } => {
+          let frame_end = body_start + fec::encoded_len(len + CHECKSUM_BYTES, self.fec_parity);
+          if self.framing == Framing::Cobs && self.incoming_message.len() != frame_end {
+            error!("den.read: Incoming COBS frame longer than its length says");
+            self.drop_frame();
+            return Err(nb::Error::Other(DencoderError::BadFraming));
+          }
+          // (With FEC, if this was a false frame start, repairs scramble the bytes resync would rescan;
+          // but a false start has already beaten the length checksum, so that's rare.)
+          if let Err(e) = Self::check_body(&mut self.incoming_message[body_start..frame_end], len, self.fec_parity, &mut self.stats.fec) {
+            self.resync();
+            return Err(nb::Error::Other(e.cast()));
+          }
+          let msg = &self.incoming_message[body_start..body_start + len];
+          buffer.clear();
+          if buffer.extend_from_slice(msg).is_err() {
+            // Only if `buffer` shrank since we checked CAPACITY, but still
+            self.resync();
+            return Err(nb::Error::Other(DencoderError::MessageTooBig));
+          }
+          if self.framing == Framing::Cobs {
+            self.drop_frame();
+          } else {
+            // Anything after the frame is left over from a resync; keep it for next time
+            self.incoming_message.drain(0..frame_end);
+            self.phase = RxPhase::Magic;
+          }
+
+          self.source.after_rx();
+          trace!("<--den.read");
+          return Ok(());
+        },
+      }
     }
   }
 }
@@ -0,0 +1,239 @@
+// Async Encoder and Decoder, for embassy (`embedded-io-async` feature) and tokio (`tokio` feature).
+//
+// These don't frame anything themselves: AsyncEncoder runs a plain Encoder into a VecSink and writes
+// out whatever collects, and AsyncDecoder reads into a plain Decoder's BufferSource whenever it wants more.
+// So the wire format and error behavior are exactly the blocking ones.
+
+use core::future::Future;
+
+use heapless::Vec;
+
+use super::checksum::{Checksum, Sha256Checksum};
+use super::transport::{BufferSource, VecSink};
+use super::{Decoder, DecoderT, DecoderStats, DencoderError, Encoder, Endianness, FecStats, Framing};
+
+/// Most bytes AsyncDecoder asks its reader for at once.
+const READ_CHUNK_BYTES: usize = 64;
+
+/**
+ * Where an AsyncEncoder's bytes go.  Any `embedded_io_async::Write` is one; wrap tokio writers in `TokioIo`.
+ */
+pub trait AsyncSink {
+  type Error;
+
+  fn write_all(&mut self, buf: &[u8]) -> impl Future<Output = Result<(), Self::Error>>;
+
+  fn flush(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/**
+ * Where an AsyncDecoder's bytes come from.  Any `embedded_io_async::Read` is one; wrap tokio readers in `TokioIo`.
+ */
+pub trait AsyncSource {
+  type Error;
+
+  /// Waits for at least one byte, and reads up to `buf.len()`.  Returns 0 at end of stream.
+  fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = Result<usize, Self::Error>>;
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl <T: embedded_io_async::Write> AsyncSink for T {
+  type Error = T::Error;
+
+  async fn write_all(&mut self, buf: &[u8]) -> Result<(), T::Error> {
+    return embedded_io_async::Write::write_all(self, buf).await;
+  }
+
+  async fn flush(&mut self) -> Result<(), T::Error> {
+    return embedded_io_async::Write::flush(self).await;
+  }
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl <T: embedded_io_async::Read> AsyncSource for T {
+  type Error = T::Error;
+
+  async fn read(&mut self, buf: &mut [u8]) -> Result<usize, T::Error> {
+    return embedded_io_async::Read::read(self, buf).await;
+  }
+}
+
+/// A tokio `AsyncRead` and/or `AsyncWrite`, as an AsyncSource and/or AsyncSink.
+#[cfg(feature = "tokio")]
+pub struct TokioIo<T>(pub T);
+
+#[cfg(feature = "tokio")]
+impl <T: tokio::io::AsyncWrite + Unpin> AsyncSink for TokioIo<T> {
+  type Error = std::io::Error;
+
+  async fn write_all(&mut self, buf: &[u8]) -> Result<(), std::io::Error> {
+    return tokio::io::AsyncWriteExt::write_all(&mut self.0, buf).await;
+  }
+
+  async fn flush(&mut self) -> Result<(), std::io::Error> {
+    return tokio::io::AsyncWriteExt::flush(&mut self.0).await;
+  }
+}
+
+#[cfg(feature = "tokio")]
+impl <T: tokio::io::AsyncRead + Unpin> AsyncSource for TokioIo<T> {
+  type Error = std::io::Error;
+
+  async fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+    return tokio::io::AsyncReadExt::read(&mut self.0, buf).await;
+  }
+}
+
+/**
+ * Encoder over an AsyncSink.  BUF is how many bytes of frame are collected before each write to the
+ * sink; frames longer than that just go out in several writes.
+ */
+pub struct AsyncEncoder<const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, W, const BUF: usize, CK = Sha256Checksum> {
+  encoder: Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, VecSink<BUF>, CK>,
+  writer: W,
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, W: AsyncSink, const BUF: usize, CK: Checksum> AsyncEncoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, W, BUF, CK> {
+  pub fn new(writer: W) -> Self {
+    return AsyncEncoder {
+      encoder: Encoder::new(VecSink::new()),
+      writer: writer,
+    };
+  }
+
+  /// See `Encoder::with_framing`.
+  pub fn with_framing(mut self, framing: Framing) -> Self {
+    self.encoder = self.encoder.with_framing(framing);
+    return self;
+  }
+
+  /// See `Encoder::with_endianness`.
+  pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+    self.encoder = self.encoder.with_endianness(endianness);
+    return self;
+  }
+
+  /// See `Encoder::with_fec`.
+  pub fn with_fec(mut self, correctable: usize) -> Self {
+    self.encoder = self.encoder.with_fec(correctable);
+    return self;
+  }
+
+  /// See `Encoder::with_header`.
+  pub fn with_header(mut self, header: bool) -> Self {
+    self.encoder = self.encoder.with_header(header);
+    return self;
+  }
+
+  pub fn writer(&self) -> &W {
+    return &self.writer;
+  }
+
+  pub fn writer_mut(&mut self) -> &mut W {
+    return &mut self.writer;
+  }
+
+  /**
+   * Encodes `msg` and writes the frame out, then flushes.
+   * If a previous call was cancelled partway, the rest of that frame is dropped; the receiver
+   * will see it fail and resync.
+   */
+  pub async fn write_frame(&mut self, msg: &[u8]) -> Result<(), DencoderError<W::Error>> {
+    const { assert!(BUF > 0, "AsyncEncoder needs a BUF of at least 1") };
+    self.encoder.sink_mut().take();
+    let mut frame = self.encoder.begin(msg.len()).map_err(|e| e.cast())?;
+    // BUF bytes of message at a time, so a chunk that blocks is all that gets encoded over again
+    for chunk in msg.chunks(BUF).map(Some).chain([None]) {
+      loop {
+        let r = match chunk {
+          Some(c) => frame.write_chunk(c),
+          None => frame.finish(),
+        };
+        let out = frame.sink_mut().take();
+        self.writer.write_all(&out).await.map_err(DencoderError::Transport)?;
+        match r {
+          Ok(()) => break,
+          Err(nb::Error::WouldBlock) => continue,
+          Err(nb::Error::Other(e)) => return Err(e.cast()),
+        };
+      }
+    }
+    self.writer.flush().await.map_err(DencoderError::Transport)?;
+    return Ok(());
+  }
+}
+
+/**
+ * Decoder over an AsyncSource.  BUF_SIZE is as for Decoder, and also bounds how much is read ahead.
+ */
+pub struct AsyncDecoder<const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, R, const BUF_SIZE: usize, CK = Sha256Checksum> {
+  decoder: Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, BufferSource<BUF_SIZE>, BUF_SIZE, CK>,
+  reader: R,
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, R: AsyncSource, const BUF_SIZE: usize, CK: Checksum> AsyncDecoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, R, BUF_SIZE, CK> {
+  pub fn new(reader: R) -> Self {
+    return AsyncDecoder {
+      decoder: Decoder::new_plain(),
+      reader: reader,
+    };
+  }
+
+  /// See `Decoder::with_framing`.
+  pub fn with_framing(mut self, framing: Framing) -> Self {
+    self.decoder = self.decoder.with_framing(framing);
+    return self;
+  }
+
+  /// See `Decoder::with_endianness`.
+  pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+    self.decoder = self.decoder.with_endianness(endianness);
+    return self;
+  }
+
+  /// See `Decoder::with_fec`.
+  pub fn with_fec(mut self, correctable: usize) -> Self {
+    self.decoder = self.decoder.with_fec(correctable);
+    return self;
+  }
+
+  pub fn fec_stats(&self) -> FecStats {
+    return self.decoder.fec_stats();
+  }
+
+  /// See `Decoder::stats`.
+  pub fn stats(&self) -> DecoderStats {
+    return self.decoder.stats();
+  }
+
+  pub fn reader(&self) -> &R {
+    return &self.reader;
+  }
+
+  pub fn reader_mut(&mut self) -> &mut R {
+    return &mut self.reader;
+  }
+
+  /**
+   * Reads until a whole message is in `buffer`.  Frames that fail validation come back as errors,
+   * as from `DecoderT::read`; just call again.  Returns `DencoderError::Eof` if the reader runs out.
+   * Safe to cancel (if the reader's `read` is): nothing's taken from the reader but what's already kept.
+   */
+  pub async fn read_frame<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>) -> Result<(), DencoderError<R::Error>> {
+    loop {
+      match self.decoder.read(buffer) {
+        Ok(()) => return Ok(()),
+        Err(nb::Error::WouldBlock) => (),
+        Err(nb::Error::Other(e)) => return Err(e.cast()),
+      };
+      // The Decoder only blocks once it's used up everything pending, so there's room
+      let mut chunk = [0u8; READ_CHUNK_BYTES];
+      let n = chunk.len().min(self.decoder.source().space());
+      let n = self.reader.read(&mut chunk[..n]).await.map_err(DencoderError::Transport)?;
+      if n == 0 {
+        return Err(DencoderError::Eof);
+      }
+      let _ = self.decoder.add(&chunk[..n]);
+    }
+  }
+}
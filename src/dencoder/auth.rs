@@ -0,0 +1,235 @@
+// Authenticated messages, for links where someone else could be sending: HMAC-SHA256 with a pre-shared key.
+//
+// Every dencoder message is [counter (8 bytes, big-endian), payload, tag (TAG_BYTES)], where the tag
+// is the first TAG_BYTES of HMAC-SHA256 over counter and payload.  The counter goes up by one per
+// message, and the receiver only accepts counters newer than the last it accepted, so recorded
+// frames can't be played back.  (The frame checksum stays as it is; it catches noise before we get
+// as far as the tag.)
+//
+// Nothing is encrypted; anyone listening can read the messages, they just can't forge or replay them.
+// Use a different key for each direction, or frames could be reflected back at their sender.
+
+use sha2::{Digest, Sha256};
+
+use heapless::Vec;
+
+use super::{DecoderT, DencoderError, EncoderT};
+
+/// Bytes of the message counter.
+pub const COUNTER_BYTES: usize = 8;
+/// Bytes of the (truncated) HMAC tag.
+pub const TAG_BYTES: usize = 16;
+/// Bytes each dencoder message gains from authentication.
+pub const AUTH_OVERHEAD_BYTES: usize = COUNTER_BYTES + TAG_BYTES;
+/// Most parts `AuthEncoder::write_vectored` takes.
+pub const MAX_PARTS: usize = 8;
+
+const BLOCK_BYTES: usize = 64;
+
+/**
+ * HMAC-SHA256 (RFC 2104) with a fixed key.  Keeps the hash states with the key already absorbed,
+ * so each tag costs just the message and two blocks.
+ */
+#[derive(Clone)]
+pub struct HmacSha256 {
+  inner: Sha256,
+  outer: Sha256,
+}
+
+impl HmacSha256 {
+  /// Any length of key works, but there's no point going past 64 bytes; longer keys get hashed down to 32.
+  pub fn new(key: &[u8]) -> HmacSha256 {
+    let mut block = [0u8; BLOCK_BYTES];
+    if key.len() > BLOCK_BYTES {
+      block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+      block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5C));
+    return HmacSha256 {
+      inner: inner,
+      outer: outer,
+    };
+  }
+
+  /// The full tag over `parts` concatenated.
+  pub fn tag(&self, parts: &[&[u8]]) -> [u8; 32] {
+    return self.tag_iter(parts.iter().copied());
+  }
+
+  fn tag_iter<'p>(&self, parts: impl Iterator<Item = &'p [u8]>) -> [u8; 32] {
+    let mut inner = self.inner.clone();
+    for p in parts {
+      inner.update(p);
+    }
+    let mut outer = self.outer.clone();
+    outer.update(inner.finalize());
+    return outer.finalize().into();
+  }
+
+  /// Whether `tag` (not empty) matches the start of the tag over `parts`.  Takes the same time wherever they differ.
+  pub fn verify(&self, parts: &[&[u8]], tag: &[u8]) -> bool {
+    let expected = self.tag(parts);
+    if tag.is_empty() || tag.len() > expected.len() {
+      return false;
+    }
+    return expected.iter().zip(tag).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+  }
+}
+
+/**
+ * Authenticates each message before handing it to an Encoder (so the Encoder must take messages
+ * AUTH_OVERHEAD_BYTES longer).  It's an EncoderT itself, so it can go under the other layers.
+ */
+pub struct AuthEncoder<ENC> {
+  encoder: ENC,
+  hmac: HmacSha256,
+  counter: u64, // For the next message
+}
+
+impl <ENC: EncoderT> AuthEncoder<ENC> {
+  pub fn new(encoder: ENC, key: &[u8]) -> Self {
+    return AuthEncoder {
+      encoder: encoder,
+      hmac: HmacSha256::new(key),
+      counter: 0,
+    };
+  }
+
+  /**
+   * Sets the next message's counter.  The receiver drops anything not newer than what it's seen,
+   * so if the sender can restart, save `counter` somewhere that survives it and carry on from there.
+   */
+  pub fn with_counter(mut self, counter: u64) -> Self {
+    self.counter = counter;
+    return self;
+  }
+
+  /// Counter of the next message.
+  pub fn counter(&self) -> u64 {
+    return self.counter;
+  }
+
+  pub fn encoder(&self) -> &ENC {
+    return &self.encoder;
+  }
+
+  pub fn encoder_mut(&mut self) -> &mut ENC {
+    return &mut self.encoder;
+  }
+
+  pub fn into_inner(self) -> ENC {
+    return self.encoder;
+  }
+}
+
+impl <ENC: EncoderT> EncoderT for AuthEncoder<ENC> {
+  type Error = ENC::Error;
+
+  /// As `Encoder::write`.  Call again with the same `msg` after WouldBlock, and it goes out with the same counter.
+  fn write(&mut self, msg: &[u8]) -> Result<(), nb::Error<DencoderError<ENC::Error>>> {
+    return self.write_vectored(&[msg]);
+  }
+
+  /**
+   * As `Encoder::write_vectored`.  Panics if there are more than MAX_PARTS parts.
+   * Once the counter gets to u64::MAX there's no next one, so this sends nothing and returns CounterExhausted.
+   */
+  fn write_vectored(&mut self, parts: &[&[u8]]) -> Result<(), nb::Error<DencoderError<ENC::Error>>> {
+    assert!(parts.len() <= MAX_PARTS, "AuthEncoder::write_vectored: at most {} parts", MAX_PARTS);
+    let next = match self.counter.checked_add(1) {
+      Some(next) => next,
+      None => return Err(nb::Error::Other(DencoderError::CounterExhausted)),
+    };
+    let counter = self.counter.to_be_bytes();
+    let tag = self.hmac.tag_iter(core::iter::once(&counter[..]).chain(parts.iter().copied()));
+    let mut pieces: Vec<&[u8], { MAX_PARTS + 2 }> = Vec::new();
+    let _ = pieces.push(&counter); // Checked the length above
+    let _ = pieces.extend_from_slice(parts);
+    let _ = pieces.push(&tag[..TAG_BYTES]);
+    let r = self.encoder.write_vectored(&pieces);
+    if !matches!(r, Err(nb::Error::WouldBlock)) {
+      // Never reuse a counter, even if the frame failed; the receiver may have seen part of it
+      self.counter = next;
+    }
+    return r;
+  }
+}
+
+/**
+ * Reads messages from a Decoder and checks them, dropping forgeries (`DencoderError::Unauthenticated`)
+ * and replays (`DencoderError::Replayed`).  It's a DecoderT itself, so it can go under the other layers.
+ */
+pub struct AuthDecoder<DEC> {
+  decoder: DEC,
+  hmac: HmacSha256,
+  last_counter: Option<u64>,
+}
+
+impl <DEC: DecoderT> AuthDecoder<DEC> {
+  pub fn new(decoder: DEC, key: &[u8]) -> Self {
+    return AuthDecoder {
+      decoder: decoder,
+      hmac: HmacSha256::new(key),
+      last_counter: None,
+    };
+  }
+
+  /**
+   * Only accepts messages with counters after `counter`, e.g. the last one accepted before a restart.
+   */
+  pub fn with_last_counter(mut self, counter: u64) -> Self {
+    self.last_counter = Some(counter);
+    return self;
+  }
+
+  /// Counter of the last message accepted, if any.
+  pub fn last_counter(&self) -> Option<u64> {
+    return self.last_counter;
+  }
+
+  pub fn decoder(&self) -> &DEC {
+    return &self.decoder;
+  }
+
+  pub fn decoder_mut(&mut self) -> &mut DEC {
+    return &mut self.decoder;
+  }
+
+  pub fn into_inner(self) -> DEC {
+    return self.decoder;
+  }
+}
+
+impl <DEC: DecoderT> DecoderT for AuthDecoder<DEC> {
+  type Error = DEC::Error;
+
+  /**
+   * As `Decoder::read`, but `buffer` needs AUTH_OVERHEAD_BYTES of room more than the message, and
+   * it's dropped with Unauthenticated or Replayed if it doesn't check out.
+   */
+  fn read<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>) -> Result<(), nb::Error<DencoderError<DEC::Error>>> {
+    self.decoder.read(buffer)?;
+    if buffer.len() < AUTH_OVERHEAD_BYTES {
+      return Err(nb::Error::Other(DencoderError::Unauthenticated));
+    }
+    let (signed, tag) = buffer.split_at(buffer.len() - TAG_BYTES);
+    if !self.hmac.verify(&[signed], tag) {
+      return Err(nb::Error::Other(DencoderError::Unauthenticated));
+    }
+    let mut counter = [0u8; COUNTER_BYTES];
+    counter.copy_from_slice(&signed[..COUNTER_BYTES]);
+    let counter = u64::from_be_bytes(counter);
+    if self.last_counter.is_some_and(|last| counter <= last) {
+      return Err(nb::Error::Other(DencoderError::Replayed));
+    }
+    self.last_counter = Some(counter);
+    let len = signed.len() - COUNTER_BYTES;
+    buffer.copy_within(COUNTER_BYTES..COUNTER_BYTES + len, 0);
+    buffer.truncate(len);
+    return Ok(());
+  }
+}
@@ -0,0 +1,228 @@
+// Several logical channels over one Encoder/Decoder pair.
+//
+// Every dencoder message carries a 1-byte header, the channel number (0 to CHANNELS - 1), then the payload.
+// Each channel has its own send and receive queue.  When the Encoder's free, the next frame comes
+// from the highest-priority channel with something queued (round-robin between equals), so e.g.
+// control messages don't wait behind a backlog of telemetry; at worst they wait for the one frame
+// already going out.
+
+use heapless::{Deque, Vec};
+
+use super::{DecoderT, DencoderError, EncoderT};
+
+/// Bytes of each dencoder message taken up by the channel header.
+pub const CHANNEL_HEADER_BYTES: usize = 1;
+
+/// `TE` and `RE` are the transport error types of the Encoder and Decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MuxError<TE, RE> {
+  /// The channel number is CHANNELS or more.
+  NoSuchChannel,
+  /// The message is longer than FRAME_SIZE - CHANNEL_HEADER_BYTES.
+  MessageTooLong,
+  /// The next message on the channel is bigger than the buffer passed to `receive`.  It's still
+  /// queued; pass a bigger buffer.
+  MessageTooBig,
+  /// The Encoder failed.  The frame it was writing was dropped.
+  Send(DencoderError<TE>),
+  /// The Decoder's transport failed.  (Frames that just fail validation are dropped and counted.)
+  Receive(RE),
+}
+
+/// The MuxError for a given Encoder and Decoder.
+pub type MuxLinkError<ENC, DEC> = MuxError<<ENC as EncoderT>::Error, <DEC as DecoderT>::Error>;
+
+/// Counts since the Multiplexer was made.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MuxStats {
+  /// Frames the Decoder dropped as damaged, or that made no sense (empty, or for a channel we don't have).
+  pub bad_frames: u32,
+  /// Messages received for a channel whose queue was already full, and dropped.
+  pub overflows: u32,
+}
+
+/**
+ * Sends and receives messages on CHANNELS separate channels over one Encoder and Decoder; the other
+ * end needs a Multiplexer with the same channels.  FRAME_SIZE is the largest dencoder message, so
+ * messages can be up to FRAME_SIZE - CHANNEL_HEADER_BYTES; the Decoders on both ends must accept
+ * that.  Each channel queues up to DEPTH messages each way, taking 2 * CHANNELS * DEPTH * FRAME_SIZE bytes.
+ * Nothing happens on its own; call `poll` regularly, both to receive and to drive sending.
+ */
+pub struct Multiplexer<ENC, DEC, const CHANNELS: usize, const FRAME_SIZE: usize, const DEPTH: usize> {
+  encoder: ENC,
+  decoder: DEC,
+  priorities: [u8; CHANNELS],
+  tx_queues: [Deque<Vec<u8, FRAME_SIZE>, DEPTH>; CHANNELS], // Header included
+  rx_queues: [Deque<Vec<u8, FRAME_SIZE>, DEPTH>; CHANNELS], // Header included
+  tx_channel: Option<usize>, // The channel whose first frame `encoder.write` is partway through, if any
+  last_tx: usize, // Channel last sent from, for round-robin
+  rx_frame: Vec<u8, FRAME_SIZE>,
+  stats: MuxStats,
+}
+
+impl <ENC: EncoderT, DEC: DecoderT, const CHANNELS: usize, const FRAME_SIZE: usize, const DEPTH: usize> Multiplexer<ENC, DEC, CHANNELS, FRAME_SIZE, DEPTH> {
+  /// All channels start at priority 0; see `with_priority`.
+  pub fn new(encoder: ENC, decoder: DEC) -> Self {
+    const { assert!(CHANNELS > 0 && CHANNELS <= 256, "CHANNELS must be 1..=256, to fit the channel header") };
+    const { assert!(FRAME_SIZE >= CHANNEL_HEADER_BYTES) };
+    return Multiplexer {
+      encoder: encoder,
+      decoder: decoder,
+      priorities: [0; CHANNELS],
+      tx_queues: core::array::from_fn(|_| Deque::new()),
+      rx_queues: core::array::from_fn(|_| Deque::new()),
+      tx_channel: None,
+      last_tx: CHANNELS - 1,
+      rx_frame: Vec::new(),
+      stats: MuxStats::default(),
+    };
+  }
+
+  /**
+   * Sets `channel`'s send priority; higher goes first.  Only affects this end's sending.
+   * Panics if there's no such channel.
+   */
+  pub fn with_priority(mut self, channel: usize, priority: u8) -> Self {
+    self.priorities[channel] = priority;
+    return self;
+  }
+
+  pub fn stats(&self) -> MuxStats {
+    return self.stats;
+  }
+
+  pub fn encoder(&self) -> &ENC {
+    return &self.encoder;
+  }
+
+  pub fn encoder_mut(&mut self) -> &mut ENC {
+    return &mut self.encoder;
+  }
+
+  pub fn decoder(&self) -> &DEC {
+    return &self.decoder;
+  }
+
+  pub fn decoder_mut(&mut self) -> &mut DEC {
+    return &mut self.decoder;
+  }
+
+  /// Number of messages waiting to go out on `channel` (including one partway out).  0 if there's no such channel.
+  pub fn queued(&self, channel: usize) -> usize {
+    return self.tx_queues.get(channel).map_or(0, |q| q.len());
+  }
+
+  /// Number of messages received on `channel` and not yet taken.  0 if there's no such channel.
+  pub fn pending(&self, channel: usize) -> usize {
+    return self.rx_queues.get(channel).map_or(0, |q| q.len());
+  }
+
+  /**
+   * Queues `msg` to go out on `channel` on the next `poll`.  Returns WouldBlock if the channel
+   * already has DEPTH messages waiting; `poll` to send some, then try again.
+   */
+  pub fn send(&mut self, channel: usize, msg: &[u8]) -> Result<(), nb::Error<MuxLinkError<ENC, DEC>>> {
+    if channel >= CHANNELS {
+      return Err(nb::Error::Other(MuxError::NoSuchChannel));
+    }
+    if msg.len() > FRAME_SIZE - CHANNEL_HEADER_BYTES {
+      return Err(nb::Error::Other(MuxError::MessageTooLong));
+    }
+    let queue = &mut self.tx_queues[channel];
+    if queue.is_full() {
+      return Err(nb::Error::WouldBlock);
+    }
+    let mut frame = Vec::new();
+    let _ = frame.push(channel as u8); // Checked the length above
+    let _ = frame.extend_from_slice(msg);
+    let _ = queue.push_back(frame);
+    return Ok(());
+  }
+
+  /**
+   * Takes the next message received on `channel` into `buffer`.  Returns WouldBlock if there isn't
+   * one; `poll` to read in more.
+   */
+  pub fn receive<const CAPACITY: usize>(&mut self, channel: usize, buffer: &mut Vec<u8, CAPACITY>) -> Result<(), nb::Error<MuxLinkError<ENC, DEC>>> {
+    let queue = self.rx_queues.get_mut(channel).ok_or(nb::Error::Other(MuxError::NoSuchChannel))?;
+    let frame = queue.front().ok_or(nb::Error::WouldBlock)?;
+    buffer.clear();
+    if buffer.extend_from_slice(&frame[CHANNEL_HEADER_BYTES..]).is_err() {
+      return Err(nb::Error::Other(MuxError::MessageTooBig));
+    }
+    queue.pop_front();
+    return Ok(());
+  }
+
+  /**
+   * Does everything there is to do: reads in whatever the Decoder has, sorting it onto the channels'
+   * queues, and writes out queued messages until the Encoder blocks or there are none left.
+   */
+  pub fn poll(&mut self) -> Result<(), MuxLinkError<ENC, DEC>> {
+    self.receive_all()?;
+    return match self.transmit() {
+      Ok(()) | Err(nb::Error::WouldBlock) => Ok(()),
+      Err(nb::Error::Other(e)) => Err(e),
+    };
+  }
+
+  fn receive_all(&mut self) -> Result<(), MuxLinkError<ENC, DEC>> {
+    loop {
+      match self.decoder.read(&mut self.rx_frame) {
+        Ok(()) => (),
+        Err(nb::Error::WouldBlock) => return Ok(()),
+        Err(nb::Error::Other(DencoderError::Transport(e))) => return Err(MuxError::Receive(e)),
+        Err(nb::Error::Other(_)) => {
+          self.stats.bad_frames += 1;
+          continue;
+        },
+      };
+      let Some(queue) = self.rx_frame.first().and_then(|&c| self.rx_queues.get_mut(c as usize)) else {
+        self.stats.bad_frames += 1;
+        continue;
+      };
+      if queue.push_back(self.rx_frame.clone()).is_err() {
+        self.stats.overflows += 1;
+      }
+    }
+  }
+
+  /**
+   * Writes frames until there's nothing left to send or the Encoder blocks.
+   */
+  fn transmit(&mut self) -> Result<(), nb::Error<MuxLinkError<ENC, DEC>>> {
+    loop {
+      let channel = match self.tx_channel.or_else(|| self.next_channel()) {
+        Some(channel) => channel,
+        None => return Ok(()),
+      };
+      let queue = &mut self.tx_queues[channel];
+      let Some(frame) = queue.front() else {
+        return Ok(()); // Can't happen; next_channel only picks channels with something queued
+      };
+      self.tx_channel = Some(channel);
+      let r = self.encoder.write(frame);
+      if let Err(nb::Error::WouldBlock) = r {
+        return Err(nb::Error::WouldBlock);
+      }
+      queue.pop_front();
+      self.tx_channel = None;
+      self.last_tx = channel;
+      if let Err(nb::Error::Other(e)) = r {
+        return Err(nb::Error::Other(MuxError::Send(e)));
+      }
+    }
+  }
+
+  /// The highest-priority channel with something to send, starting the search after the last one sent from.
+  fn next_channel(&self) -> Option<usize> {
+    let mut best: Option<usize> = None;
+    for i in 1..=CHANNELS {
+      let c = (self.last_tx + i) % CHANNELS;
+      if !self.tx_queues[c].is_empty() && best.is_none_or(|b| self.priorities[c] > self.priorities[b]) {
+        best = Some(c);
+      }
+    }
+    return best;
+  }
+}
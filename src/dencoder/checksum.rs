@@ -0,0 +1,206 @@
+// Checksum algorithms for the dencoder's length and message checksums.
+
+use crc::{Crc, Digest as CrcDigest, CRC_16_IBM_3740, CRC_32_ISO_HDLC, CRC_8_SMBUS};
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh32::Xxh32;
+
+/// Longest checksum any algorithm here produces; CHECKSUM_BYTES can't usefully be more than this.
+pub const MAX_CHECKSUM_BYTES: usize = 32;
+
+/// `Checksum::ID` for algorithms not defined here.  IDs only go up to 7; see the frame header.
+pub const CUSTOM_CHECKSUM_ID: u8 = 7;
+
+/**
+ * A checksum the dencoder can use for both the length checksum (truncated to LEN_PREFIX_BYTES) and
+ * the message checksum (truncated to CHECKSUM_BYTES).  Output is the algorithm's value, big-endian,
+ * zero-padded to MAX_CHECKSUM_BYTES; a CHECKSUM_BYTES longer than OUTPUT_BYTES just carries zeros.
+ */
+pub trait Checksum: Default {
+  /// Number of meaningful bytes `finalize` produces.
+  const OUTPUT_BYTES: usize;
+
+  /// Identifies the algorithm in frame headers, so a Decoder can tell a mismatched sender from noise.
+  const ID: u8 = CUSTOM_CHECKSUM_ID;
+
+  fn update(&mut self, data: &[u8]);
+
+  fn finalize(self) -> [u8; MAX_CHECKSUM_BYTES];
+
+  fn digest(data: &[u8]) -> [u8; MAX_CHECKSUM_BYTES] {
+    let mut c = Self::default();
+    c.update(data);
+    return c.finalize();
+  }
+}
+
+fn pad(value: &[u8]) -> [u8; MAX_CHECKSUM_BYTES] {
+  let mut out = [0u8; MAX_CHECKSUM_BYTES];
+  out[..value.len()].copy_from_slice(value);
+  return out;
+}
+
+/// SHA-256.  The original dencoder checksum, and still the default.
+#[derive(Default)]
+pub struct Sha256Checksum(Sha256);
+
+impl Checksum for Sha256Checksum {
+  const OUTPUT_BYTES: usize = 32;
+  const ID: u8 = 1;
+
+  fn update(&mut self, data: &[u8]) {
+    self.0.update(data);
+  }
+
+  fn finalize(self) -> [u8; MAX_CHECKSUM_BYTES] {
+    return self.0.finalize().into();
+  }
+}
+
+static CRC8: Crc<u8> = Crc::<u8>::new(&CRC_8_SMBUS);
+static CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_3740);
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// CRC-8/SMBUS (poly 0x07).
+pub struct Crc8(CrcDigest<'static, u8>);
+
+impl Default for Crc8 {
+  fn default() -> Self {
+    return Crc8(CRC8.digest());
+  }
+}
+
+impl Checksum for Crc8 {
+  const OUTPUT_BYTES: usize = 1;
+  const ID: u8 = 2;
+
+  fn update(&mut self, data: &[u8]) {
+    self.0.update(data);
+  }
+
+  fn finalize(self) -> [u8; MAX_CHECKSUM_BYTES] {
+    return pad(&[self.0.finalize()]);
+  }
+}
+
+/// CRC-16/IBM-3740, a.k.a. CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF).
+pub struct Crc16(CrcDigest<'static, u16>);
+
+impl Default for Crc16 {
+  fn default() -> Self {
+    return Crc16(CRC16.digest());
+  }
+}
+
+impl Checksum for Crc16 {
+  const OUTPUT_BYTES: usize = 2;
+  const ID: u8 = 3;
+
+  fn update(&mut self, data: &[u8]) {
+    self.0.update(data);
+  }
+
+  fn finalize(self) -> [u8; MAX_CHECKSUM_BYTES] {
+    return pad(&self.0.finalize().to_be_bytes());
+  }
+}
+
+/// CRC-32/ISO-HDLC, the zlib/Ethernet one.
+pub struct Crc32(CrcDigest<'static, u32>);
+
+impl Default for Crc32 {
+  fn default() -> Self {
+    return Crc32(CRC32.digest());
+  }
+}
+
+impl Checksum for Crc32 {
+  const OUTPUT_BYTES: usize = 4;
+  const ID: u8 = 4;
+
+  fn update(&mut self, data: &[u8]) {
+    self.0.update(data);
+  }
+
+  fn finalize(self) -> [u8; MAX_CHECKSUM_BYTES] {
+    return pad(&self.0.finalize().to_be_bytes());
+  }
+}
+
+/// Fletcher-16.  No tables, no multiplies; about the cheapest thing that still catches reordering.
+#[derive(Default)]
+pub struct Fletcher16 {
+  sum1: u16,
+  sum2: u16,
+}
+
+impl Checksum for Fletcher16 {
+  const OUTPUT_BYTES: usize = 2;
+  const ID: u8 = 5;
+
+  fn update(&mut self, data: &[u8]) {
+    for &b in data {
+      self.sum1 = (self.sum1 + b as u16) % 255;
+      self.sum2 = (self.sum2 + self.sum1) % 255;
+    }
+  }
+
+  fn finalize(self) -> [u8; MAX_CHECKSUM_BYTES] {
+    return pad(&((self.sum2 << 8) | self.sum1).to_be_bytes());
+  }
+}
+
+/// xxHash32, seed 0.  Fast on 32-bit cores, but not designed for catching burst errors like a CRC is.
+pub struct XxHash32(Xxh32);
+
+impl Default for XxHash32 {
+  fn default() -> Self {
+    return XxHash32(Xxh32::new(0));
+  }
+}
+
+impl Checksum for XxHash32 {
+  const OUTPUT_BYTES: usize = 4;
+  const ID: u8 = 6;
+
+  fn update(&mut self, data: &[u8]) {
+    self.0.update(data);
+  }
+
+  fn finalize(self) -> [u8; MAX_CHECKSUM_BYTES] {
+    return pad(&self.0.digest().to_be_bytes());
+  }
+}
+
+/// No checksum at all; every checksum byte is zero.  Only for links that are already reliable,
+/// since the length checksum is what lets the decoder tell real frames from noise.
+#[derive(Default)]
+pub struct NoChecksum;
+
+impl Checksum for NoChecksum {
+  const OUTPUT_BYTES: usize = 0;
+  const ID: u8 = 0;
+
+  fn update(&mut self, _data: &[u8]) {
+  }
+
+  fn finalize(self) -> [u8; MAX_CHECKSUM_BYTES] {
+    return [0u8; MAX_CHECKSUM_BYTES];
+  }
+}
+
+/**
+ * `digest` of the algorithm here whose `Checksum::ID` is `id`; None for CUSTOM_CHECKSUM_ID or an unknown ID.
+ * For checking frames from a sender that says (in its header) it's using something else.
+ */
+pub fn digest_by_id(id: u8, data: &[u8]) -> Option<[u8; MAX_CHECKSUM_BYTES]> {
+  return match id {
+    NoChecksum::ID => Some(NoChecksum::digest(data)),
+    Sha256Checksum::ID => Some(Sha256Checksum::digest(data)),
+    Crc8::ID => Some(Crc8::digest(data)),
+    Crc16::ID => Some(Crc16::digest(data)),
+    Crc32::ID => Some(Crc32::digest(data)),
+    Fletcher16::ID => Some(Fletcher16::digest(data)),
+    XxHash32::ID => Some(XxHash32::digest(data)),
+    _ => None,
+  };
+}
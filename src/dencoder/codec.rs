@@ -0,0 +1,139 @@
+// tokio-util codec for the dencoder frame format, for use with `tokio_util::codec::Framed` and friends.
+
+use std::io;
+
+use bytes::{Buf, Bytes, BytesMut};
+use heapless::Vec;
+use tokio_util::codec;
+
+use super::checksum::{Checksum, Sha256Checksum};
+use super::transport::{BufferSource, FrameSink};
+use super::{Decoder, DecoderT, DencoderError, Encoder, EncoderT, Endianness, FecStats, Framing, TransmissionStatus};
+
+struct BytesSink {
+  out: BytesMut,
+}
+
+impl FrameSink for BytesSink {
+  type Error = core::convert::Infallible;
+
+  fn tx(&mut self, buffers: &[&[u8]]) -> Result<TransmissionStatus, nb::Error<Self::Error>> {
+    for b in buffers {
+      self.out.extend_from_slice(b);
+    }
+    return Ok(TransmissionStatus::Complete);
+  }
+}
+
+/**
+ * Encodes and decodes frames exactly as Encoder and Decoder do (same defaults, same `with_*` options).
+ * BUF_SIZE is as for Decoder.
+ * A stream ends at its first error, so frames that fail validation aren't errors here; they're
+ * dropped (the Decoder resyncs as usual) and counted in `dropped_frames`.
+ */
+pub struct DencoderCodec<const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, const BUF_SIZE: usize, CK = Sha256Checksum> {
+  encoder: Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, BytesSink, CK>,
+  decoder: Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, BufferSource<BUF_SIZE>, BUF_SIZE, CK>,
+  msg: Vec<u8, BUF_SIZE>,
+  dropped_frames: u32,
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, const BUF_SIZE: usize, CK: Checksum> DencoderCodec<LEN_PREFIX_BYTES, CHECKSUM_BYTES, BUF_SIZE, CK> {
+  pub fn new() -> Self {
+    return DencoderCodec {
+      encoder: Encoder::new(BytesSink { out: BytesMut::new() }),
+      decoder: Decoder::new_plain(),
+      msg: Vec::new(),
+      dropped_frames: 0,
+    };
+  }
+
+  /// See `Encoder::with_framing`.  Applies to both directions.
+  pub fn with_framing(mut self, framing: Framing) -> Self {
+    self.encoder = self.encoder.with_framing(framing);
+    self.decoder = self.decoder.with_framing(framing);
+    return self;
+  }
+
+  /// See `Encoder::with_endianness`.  Applies to both directions.
+  pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+    self.encoder = self.encoder.with_endianness(endianness);
+    self.decoder = self.decoder.with_endianness(endianness);
+    return self;
+  }
+
+  /// See `Encoder::with_fec`.  Applies to both directions.
+  pub fn with_fec(mut self, correctable: usize) -> Self {
+    self.encoder = self.encoder.with_fec(correctable);
+    self.decoder = self.decoder.with_fec(correctable);
+    return self;
+  }
+
+  /// See `Encoder::with_header`.  Only affects what's sent; headered frames are always accepted.
+  pub fn with_header(mut self, header: bool) -> Self {
+    self.encoder = self.encoder.with_header(header);
+    return self;
+  }
+
+  pub fn fec_stats(&self) -> FecStats {
+    return self.decoder.fec_stats();
+  }
+
+  /// Number of incoming frames dropped for failing validation.
+  pub fn dropped_frames(&self) -> u32 {
+    return self.dropped_frames;
+  }
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, const BUF_SIZE: usize, CK: Checksum> Default for DencoderCodec<LEN_PREFIX_BYTES, CHECKSUM_BYTES, BUF_SIZE, CK> {
+  fn default() -> Self {
+    return DencoderCodec::new();
+  }
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, const BUF_SIZE: usize, CK: Checksum> codec::Encoder<Bytes> for DencoderCodec<LEN_PREFIX_BYTES, CHECKSUM_BYTES, BUF_SIZE, CK> {
+  type Error = io::Error;
+
+  /// A message too long for LEN_PREFIX_BYTES is InvalidInput, wrapping the DencoderError.
+  fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), io::Error> {
+    let r = self.encoder.write(&item);
+    let out = self.encoder.sink_mut().out.split();
+    match r {
+      Ok(()) => (),
+      Err(nb::Error::WouldBlock) => return Err(io::Error::from(io::ErrorKind::WouldBlock)), // BytesSink never blocks
+      Err(nb::Error::Other(e)) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+    };
+    dst.unsplit(out);
+    return Ok(());
+  }
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, const BUF_SIZE: usize, CK: Checksum> codec::Decoder for DencoderCodec<LEN_PREFIX_BYTES, CHECKSUM_BYTES, BUF_SIZE, CK> {
+  type Item = BytesMut;
+  type Error = io::Error;
+
+  /**
+   * Takes everything in `src` into the Decoder (so `src` is always left empty), a bit at a time if
+   * need be.  Bytes after the returned message stay in the Decoder for the next call.
+   */
+  fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, io::Error> {
+    loop {
+      match self.decoder.read(&mut self.msg) {
+        Ok(()) => return Ok(Some(BytesMut::from(&self.msg[..]))),
+        Err(nb::Error::WouldBlock) => (),
+        Err(nb::Error::Other(DencoderError::Transport(e))) => match e {},
+        Err(nb::Error::Other(_)) => {
+          self.dropped_frames += 1;
+          continue;
+        },
+      };
+      if src.is_empty() {
+        return Ok(None);
+      }
+      // The Decoder only blocks once it's used up everything pending, so there's room
+      let n = src.len().min(self.decoder.source().space());
+      let _ = self.decoder.add(&src[..n]);
+      src.advance(n);
+    }
+  }
+}
@@ -0,0 +1,229 @@
+// Compressed messages: LZ4 blocks (lz4_flex, no_std), one per dencoder message.
+//
+// Every dencoder message is [flag, payload].  With FLAG_LZ4 the payload is an LZ4 block of the
+// message; with FLAG_RAW it's the message as is.  The encoder sends whichever is shorter, so
+// messages that don't compress (short ones, random ones, already-compressed ones) cost one byte
+// rather than growing.  Each message is compressed on its own, so it's messages with repetition
+// inside them that gain; lots of small distinct messages won't.
+//
+// lz4_flex's compressor keeps a hash table on the stack, 8K for messages under 64K.
+
+use heapless::Vec;
+use lz4_flex::block::{compress_into, decompress_into, get_maximum_output_size, DecompressError};
+
+use super::{DecoderT, DencoderError, EncoderT};
+
+/// Flag of a message sent as is.
+pub const FLAG_RAW: u8 = 0;
+/// Flag of a message sent as an LZ4 block.
+pub const FLAG_LZ4: u8 = 1;
+/// Bytes each dencoder message gains at worst.
+pub const COMPRESS_HEADER_BYTES: usize = 1;
+/// Most parts `CompressEncoder::write_vectored` takes.
+pub const MAX_PARTS: usize = 8;
+
+/// BUF_SIZE a CompressEncoder needs to try compressing a `msg_len` message (given in one part).
+pub const fn compress_buffer_size(msg_len: usize) -> usize {
+  return get_maximum_output_size(msg_len);
+}
+
+/// Counts for one side of a link.  Message bytes are before compression, wire bytes after (flag included).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressionStats {
+  pub messages: u32,
+  /// Messages that went compressed, rather than raw.
+  pub compressed: u32,
+  pub message_bytes: u64,
+  pub wire_bytes: u64,
+}
+
+impl CompressionStats {
+  /// Wire bytes over message bytes; under 1 is a saving.  1 if there haven't been any messages.
+  pub fn ratio(&self) -> f32 {
+    if self.message_bytes == 0 {
+      return 1.0;
+    }
+    return self.wire_bytes as f32 / self.message_bytes as f32;
+  }
+
+  fn count(&mut self, message_bytes: usize, wire_bytes: usize, compressed: bool) {
+    self.messages = self.messages.wrapping_add(1);
+    if compressed {
+      self.compressed = self.compressed.wrapping_add(1);
+    }
+    self.message_bytes = self.message_bytes.wrapping_add(message_bytes as u64);
+    self.wire_bytes = self.wire_bytes.wrapping_add(wire_bytes as u64);
+  }
+}
+
+/**
+ * Compresses each message before handing it to an Encoder (so the Encoder must take messages
+ * COMPRESS_HEADER_BYTES longer).  It's an EncoderT itself, so it can go under the other layers.
+ *
+ * Compression goes into a buffer of BUF_SIZE, which LZ4 needs `compress_buffer_size(len)` of;
+ * longer messages go raw.  Messages in several parts are gathered into the front of the same
+ * buffer first, so need room for both.
+ */
+pub struct CompressEncoder<ENC, const BUF_SIZE: usize> {
+  encoder: ENC,
+  buffer: [u8; BUF_SIZE],
+  stats: CompressionStats,
+}
+
+impl <ENC: EncoderT, const BUF_SIZE: usize> CompressEncoder<ENC, BUF_SIZE> {
+  pub fn new(encoder: ENC) -> Self {
+    return CompressEncoder {
+      encoder: encoder,
+      buffer: [0; BUF_SIZE],
+      stats: CompressionStats::default(),
+    };
+  }
+
+  /// Messages written so far, and how much they shrank.
+  pub fn stats(&self) -> CompressionStats {
+    return self.stats;
+  }
+
+  pub fn encoder(&self) -> &ENC {
+    return &self.encoder;
+  }
+
+  pub fn encoder_mut(&mut self) -> &mut ENC {
+    return &mut self.encoder;
+  }
+
+  pub fn into_inner(self) -> ENC {
+    return self.encoder;
+  }
+
+  /// Compresses `parts` into the buffer, and returns where, if it fits and is shorter than `len`.
+  fn compress(&mut self, parts: &[&[u8]], len: usize) -> Option<(usize, usize)> {
+    let r = if parts.len() == 1 {
+      compress_into(parts[0], &mut self.buffer).map(|n| (0, n))
+    } else {
+      if len + get_maximum_output_size(len) > BUF_SIZE {
+        return None;
+      }
+      let mut i = 0;
+      for p in parts {
+        self.buffer[i..i + p.len()].copy_from_slice(p);
+        i += p.len();
+      }
+      let (input, output) = self.buffer.split_at_mut(len);
+      compress_into(input, output).map(|n| (len, n))
+    };
+    return r.ok().filter(|&(_, n)| n < len);
+  }
+}
+
+impl <ENC: EncoderT, const BUF_SIZE: usize> EncoderT for CompressEncoder<ENC, BUF_SIZE> {
+  type Error = ENC::Error;
+
+  /// As `Encoder::write`.  Call again with the same `msg` after WouldBlock.
+  fn write(&mut self, msg: &[u8]) -> Result<(), nb::Error<DencoderError<ENC::Error>>> {
+    return self.write_vectored(&[msg]);
+  }
+
+  /// As `Encoder::write_vectored`.  Panics if there are more than MAX_PARTS parts.
+  fn write_vectored(&mut self, parts: &[&[u8]]) -> Result<(), nb::Error<DencoderError<ENC::Error>>> {
+    assert!(parts.len() <= MAX_PARTS, "CompressEncoder::write_vectored: at most {} parts", MAX_PARTS);
+    let len: usize = parts.iter().map(|p| p.len()).sum();
+    // Compressing is deterministic, so a retry after WouldBlock sends the same bytes
+    if let Some((start, n)) = self.compress(parts, len) {
+      self.encoder.write_vectored(&[&[FLAG_LZ4], &self.buffer[start..start + n]])?;
+      self.stats.count(len, COMPRESS_HEADER_BYTES + n, true);
+      return Ok(());
+    }
+    let mut pieces: Vec<&[u8], { MAX_PARTS + 1 }> = Vec::new();
+    let _ = pieces.push(&[FLAG_RAW]); // Checked the length above
+    let _ = pieces.extend_from_slice(parts);
+    self.encoder.write_vectored(&pieces)?;
+    self.stats.count(len, COMPRESS_HEADER_BYTES + len, false);
+    return Ok(());
+  }
+}
+
+/**
+ * Reads messages from a Decoder and decompresses them.  It's a DecoderT itself, so it can go under
+ * the other layers.  Compressed payloads are moved into a buffer of BUF_SIZE to decompress from,
+ * so BUF_SIZE only has to hold the compressed size of the longest message.
+ */
+pub struct CompressDecoder<DEC, const BUF_SIZE: usize> {
+  decoder: DEC,
+  buffer: Vec<u8, BUF_SIZE>,
+  stats: CompressionStats,
+}
+
+impl <DEC: DecoderT, const BUF_SIZE: usize> CompressDecoder<DEC, BUF_SIZE> {
+  pub fn new(decoder: DEC) -> Self {
+    return CompressDecoder {
+      decoder: decoder,
+      buffer: Vec::new(),
+      stats: CompressionStats::default(),
+    };
+  }
+
+  /// Messages read so far, and how much they'd shrunk.
+  pub fn stats(&self) -> CompressionStats {
+    return self.stats;
+  }
+
+  pub fn decoder(&self) -> &DEC {
+    return &self.decoder;
+  }
+
+  pub fn decoder_mut(&mut self) -> &mut DEC {
+    return &mut self.decoder;
+  }
+
+  pub fn into_inner(self) -> DEC {
+    return self.decoder;
+  }
+}
+
+impl <DEC: DecoderT, const BUF_SIZE: usize> DecoderT for CompressDecoder<DEC, BUF_SIZE> {
+  type Error = DEC::Error;
+
+  /**
+   * As `Decoder::read`, but `buffer` needs COMPRESS_HEADER_BYTES of room more than the message.
+   * A message that doesn't decompress is dropped with `DencoderError::Decompression`.
+   */
+  fn read<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>) -> Result<(), nb::Error<DencoderError<DEC::Error>>> {
+    self.decoder.read(buffer)?;
+    let wire_bytes = buffer.len();
+    match buffer.first() {
+      Some(&FLAG_RAW) => {
+        buffer.copy_within(COMPRESS_HEADER_BYTES.., 0);
+        buffer.truncate(wire_bytes - COMPRESS_HEADER_BYTES);
+        self.stats.count(buffer.len(), wire_bytes, false);
+        return Ok(());
+      },
+      Some(&FLAG_LZ4) => {
+        self.buffer.clear();
+        if self.buffer.extend_from_slice(&buffer[COMPRESS_HEADER_BYTES..]).is_err() {
+          buffer.clear();
+          return Err(nb::Error::Other(DencoderError::MessageTooBig));
+        }
+        let _ = buffer.resize_default(CAPACITY); // Can't fail
+        return match decompress_into(&self.buffer, buffer) {
+          Ok(n) => {
+            buffer.truncate(n);
+            self.stats.count(n, wire_bytes, true);
+            Ok(())
+          },
+          Err(e) => {
+            buffer.clear();
+            Err(nb::Error::Other(match e {
+              DecompressError::OutputTooSmall { .. } => DencoderError::MessageTooBig,
+              _ => DencoderError::Decompression,
+            }))
+          },
+        };
+      },
+      _ => {
+        buffer.clear();
+        return Err(nb::Error::Other(DencoderError::Decompression));
+      },
+    };
+  }
+}
@@ -0,0 +1,231 @@
+// Reed-Solomon forward error correction for the dencoder's message body.
+//
+// Plain RS over GF(2^8): field polynomial 0x11D, generator 2, generator polynomial roots
+// 2^0..2^(parity_bytes-1).  Blocks are systematic (data, then parity), highest-degree coefficient first,
+// same as most RS libraries with fcr = 0.  Each block can repair up to parity_bytes/2 bad bytes.
+
+/// Most bad bytes per block `with_fec` can be asked to correct.
+pub const MAX_CORRECTABLE: usize = 32;
+pub const MAX_PARITY_BYTES: usize = 2 * MAX_CORRECTABLE;
+/// Longest codeword GF(2^8) allows; a block is at most this long, parity included.
+pub const BLOCK_BYTES: usize = 255;
+
+struct Tables {
+  exp: [u8; 512], // Doubled up so `mul` needn't reduce mod 255
+  log: [u8; 256],
+}
+
+const fn build_tables() -> Tables {
+  let mut t = Tables { exp: [0; 512], log: [0; 256] };
+  let mut x: u16 = 1;
+  let mut i = 0;
+  while i < 255 {
+    t.exp[i] = x as u8;
+    t.exp[i + 255] = x as u8;
+    t.log[x as usize] = i as u8;
+    x <<= 1;
+    if x & 0x100 != 0 {
+      x ^= 0x11D;
+    }
+    i += 1;
+  }
+  t.exp[510] = t.exp[0];
+  t.exp[511] = t.exp[1];
+  return t;
+}
+
+static TABLES: Tables = build_tables();
+
+fn mul(a: u8, b: u8) -> u8 {
+  if a == 0 || b == 0 {
+    return 0;
+  }
+  return TABLES.exp[TABLES.log[a as usize] as usize + TABLES.log[b as usize] as usize];
+}
+
+fn div(a: u8, b: u8) -> u8 {
+  if a == 0 {
+    return 0;
+  }
+  return TABLES.exp[TABLES.log[a as usize] as usize + 255 - TABLES.log[b as usize] as usize];
+}
+
+/// 2^i
+fn pow2(i: usize) -> u8 {
+  return TABLES.exp[i % 255];
+}
+
+/// Wire length of `data_len` bytes once split into blocks and given `parity_bytes` of parity each.
+pub const fn encoded_len(data_len: usize, parity_bytes: usize) -> usize {
+  if parity_bytes == 0 {
+    return data_len;
+  }
+  return data_len + data_len.div_ceil(BLOCK_BYTES - parity_bytes) * parity_bytes;
+}
+
+/// Generator polynomial for `parity_bytes` of parity, highest-degree first; `[0]` is always 1.
+pub fn generator(parity_bytes: usize) -> [u8; MAX_PARITY_BYTES + 1] {
+  let mut g = [0u8; MAX_PARITY_BYTES + 1];
+  g[0] = 1;
+  for i in 0..parity_bytes {
+    // g *= (x - 2^i)
+    let root = pow2(i);
+    for j in (1..=i + 1).rev() {
+      g[j] ^= mul(g[j - 1], root);
+    }
+  }
+  return g;
+}
+
+/**
+ * Computes the parity for one block of data, given as `pieces` concatenated, into `parity`
+ * (whose length is the number of parity bytes).  `generator` is from `generator(parity.len())`.
+ */
+pub fn parity(generator: &[u8; MAX_PARITY_BYTES + 1], pieces: &[&[u8]], parity: &mut [u8]) {
+  parity.fill(0);
+  for piece in pieces {
+    update_parity(generator, piece, parity);
+  }
+}
+
+/**
+ * Carries on computing `parity` over `data`, the next part of the block.  Start from all zeros;
+ * `parity` is then the same as if it'd all been passed to `parity` at once.
+ */
+pub fn update_parity(generator: &[u8; MAX_PARITY_BYTES + 1], data: &[u8], parity: &mut [u8]) {
+  let p = parity.len();
+  for &d in data {
+    let feedback = d ^ parity[0];
+    parity.copy_within(1.., 0);
+    parity[p - 1] = 0;
+    if feedback != 0 {
+      for j in 0..p {
+        parity[j] ^= mul(generator[j + 1], feedback);
+      }
+    }
+  }
+}
+
+/**
+ * Repairs one block (data then parity) in place.  Returns the number of bytes fixed, or None if
+ * there were too many errors to fix.  Like any RS code, a block with more than parity_bytes/2
+ * errors occasionally "corrects" to the wrong data instead, so check the message checksum after.
+ */
+pub fn correct(block: &mut [u8], parity_bytes: usize) -> Option<usize> {
+  let n = block.len();
+  let mut syndromes = [0u8; MAX_PARITY_BYTES];
+  let mut clean = true;
+  for (i, syndrome) in syndromes[..parity_bytes].iter_mut().enumerate() {
+    let x = pow2(i);
+    *syndrome = block.iter().fold(0, |acc, &b| mul(acc, x) ^ b);
+    clean &= *syndrome == 0;
+  }
+  if clean {
+    return Some(0);
+  }
+
+  // Berlekamp-Massey, for the error locator (lowest-degree first)
+  let mut locator = [0u8; MAX_PARITY_BYTES + 1];
+  let mut prev = [0u8; MAX_PARITY_BYTES + 1];
+  locator[0] = 1;
+  prev[0] = 1;
+  let mut errors = 0;
+  let mut shift = 1;
+  let mut prev_discrepancy = 1;
+  for k in 0..parity_bytes {
+    let mut discrepancy = syndromes[k];
+    for i in 1..=errors {
+      discrepancy ^= mul(locator[i], syndromes[k - i]);
+    }
+    if discrepancy == 0 {
+      shift += 1;
+      continue;
+    }
+    let coef = div(discrepancy, prev_discrepancy);
+    let before = locator;
+    for i in shift..=parity_bytes {
+      locator[i] ^= mul(coef, prev[i - shift]);
+    }
+    if 2 * errors <= k {
+      errors = k + 1 - errors;
+      prev = before;
+      prev_discrepancy = discrepancy;
+      shift = 1;
+    } else {
+      shift += 1;
+    }
+  }
+  if 2 * errors > parity_bytes {
+    return None;
+  }
+
+  // Error evaluator: syndromes * locator mod x^parity_bytes
+  let mut evaluator = [0u8; MAX_PARITY_BYTES];
+  for i in 0..parity_bytes {
+    for j in 0..=i.min(errors) {
+      evaluator[i] ^= mul(syndromes[i - j], locator[j]);
+    }
+  }
+
+  // Chien search for the roots, and Forney for the error values.  Nothing's applied until we know
+  // the locator's roots all land inside the block.
+  let mut fixes = [(0usize, 0u8); MAX_CORRECTABLE];
+  let mut found = 0;
+  for pos in 0..n {
+    let x = pow2(n - 1 - pos); // Error location for this byte
+    let x_inv = div(1, x);
+    let mut l = 0;
+    let mut x_pow = 1;
+    let mut derivative = 0;
+    for (i, &c) in locator[..=errors].iter().enumerate() {
+      let term = mul(c, x_pow);
+      l ^= term;
+      if i % 2 == 1 {
+        derivative ^= mul(c, div(x_pow, x_inv)); // c * x_inv^(i-1)
+      }
+      x_pow = mul(x_pow, x_inv);
+    }
+    if l != 0 {
+      continue;
+    }
+    if derivative == 0 || found == errors {
+      return None;
+    }
+    let mut omega = 0;
+    let mut x_pow = 1;
+    for &c in &evaluator[..parity_bytes] {
+      omega ^= mul(c, x_pow);
+      x_pow = mul(x_pow, x_inv);
+    }
+    fixes[found] = (pos, mul(x, div(omega, derivative)));
+    found += 1;
+  }
+  if found != errors {
+    return None;
+  }
+  for &(pos, value) in &fixes[..found] {
+    block[pos] ^= value;
+  }
+  return Some(found);
+}
+
+/**
+ * Repairs every block of `buf` (as laid out by `encoded_len`) in place, then packs the data bytes
+ * down to the front of `buf`, parity removed.  Returns the total number of bytes fixed, or None if
+ * any block was beyond repair (in which case `buf` may be partly repaired, but isn't packed).
+ */
+pub fn correct_blocks(buf: &mut [u8], parity_bytes: usize) -> Option<usize> {
+  let mut fixed = 0;
+  for block in buf.chunks_mut(BLOCK_BYTES) {
+    fixed += correct(block, parity_bytes)?;
+  }
+  let mut w = 0;
+  let mut r = 0;
+  while r < buf.len() {
+    let data_len = (buf.len() - r).min(BLOCK_BYTES) - parity_bytes;
+    buf.copy_within(r..r + data_len, w);
+    w += data_len;
+    r += data_len + parity_bytes;
+  }
+  return Some(fixed);
+}
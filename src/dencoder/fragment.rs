@@ -0,0 +1,289 @@
+// Messages bigger than one dencoder frame, split into fragments and put back together on the other end.
+//
+// Every dencoder message carries a 5-byte header, [id, index, count, offset (2 bytes, big-endian)],
+// then the fragment's part of the message:
+// - id: which message this is part of.  Counts up by one per message, wrapping at 256.
+// - index, count: this is fragment `index` of `count` (count is at least 1; an empty message is one empty fragment).
+// - offset: where the fragment's bytes go in the message.
+// Fragments can arrive in any order, and duplicates are ignored.  Nothing is resent; if a fragment
+// is lost, the message is given up on once its others have been waiting `with_timeout`.
+
+use heapless::{Deque, Vec};
+
+use crate::rate_meter::{Duration, Instant};
+use super::{DecoderT, DencoderError, EncoderT};
+
+/// Bytes of each dencoder message taken up by the fragment header.
+pub const FRAGMENT_HEADER_BYTES: usize = 5;
+/// Most fragments one message can be split into.
+pub const MAX_FRAGMENTS: usize = 255;
+
+/// Counts since the Reassembler was made.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FragmentStats {
+  /// Fragments received that we already had, or that belong to a message already delivered.
+  pub duplicates: u32,
+  /// Messages given up on with fragments missing: timed out, or pushed out for a newer one when all SLOTS were busy.
+  pub abandoned: u32,
+  /// Frames the Decoder dropped as damaged, or fragments that made no sense (e.g. past MAX_MSG).
+  pub bad_frames: u32,
+}
+
+/**
+ * Splits messages into fragments of up to FRAME_SIZE (the largest dencoder message, header
+ * included) and writes them to an Encoder.  Messages can be up to MAX_FRAGMENTS fragments and
+ * 65535 bytes, whichever is less; the other end needs a Reassembler.
+ */
+pub struct Fragmenter<ENC, const FRAME_SIZE: usize> {
+  encoder: ENC,
+  next_id: u8,
+  next_index: usize, // Fragment of the current message to write next; nonzero only if `send` blocked partway
+}
+
+impl <ENC: EncoderT, const FRAME_SIZE: usize> Fragmenter<ENC, FRAME_SIZE> {
+  pub fn new(encoder: ENC) -> Self {
+    const { assert!(FRAME_SIZE > FRAGMENT_HEADER_BYTES, "FRAME_SIZE must leave room for some message after the fragment header") };
+    return Fragmenter {
+      encoder: encoder,
+      next_id: 0,
+      next_index: 0,
+    };
+  }
+
+  pub fn encoder(&self) -> &ENC {
+    return &self.encoder;
+  }
+
+  pub fn encoder_mut(&mut self) -> &mut ENC {
+    return &mut self.encoder;
+  }
+
+  /**
+   * Writes `msg` as however many fragments it takes.  Returns WouldBlock if the Encoder does; call
+   * again with the same `msg` to carry on.  If the Encoder fails, the rest of the message is dropped
+   * (the receiver will time it out) and the next `send` starts a new one.
+   */
+  pub fn send(&mut self, msg: &[u8]) -> Result<(), nb::Error<DencoderError<ENC::Error>>> {
+    let payload = FRAME_SIZE - FRAGMENT_HEADER_BYTES;
+    let count = msg.len().div_ceil(payload).max(1);
+    if count > MAX_FRAGMENTS || msg.len() > u16::MAX as usize {
+      return Err(nb::Error::Other(DencoderError::MessageTooLong));
+    }
+    while self.next_index < count {
+      let offset = self.next_index * payload;
+      let part = &msg[offset..(offset + payload).min(msg.len())];
+      let header = [self.next_id, self.next_index as u8, count as u8, (offset >> 8) as u8, offset as u8];
+      match self.encoder.write_vectored(&[&header, part]) {
+        Ok(()) => self.next_index += 1,
+        Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+        Err(nb::Error::Other(e)) => {
+          self.next_message();
+          return Err(nb::Error::Other(e));
+        },
+      };
+    }
+    self.next_message();
+    return Ok(());
+  }
+
+  fn next_message(&mut self) {
+    self.next_index = 0;
+    self.next_id = self.next_id.wrapping_add(1);
+  }
+}
+
+/// A message being put back together.
+struct Slot<const MAX_MSG: usize> {
+  id: u8,
+  count: u8,
+  have: [u32; 8], // Bitmap of the fragments received
+  received: usize, // Number of bits set in `have`
+  len: usize, // Length of the whole message, once the last fragment's in
+  data: Vec<u8, MAX_MSG>,
+  last_seen: Instant,
+}
+
+/**
+ * Reads fragments from a Decoder and puts the messages back together.  FRAME_SIZE is the largest
+ * dencoder message (at least the sender's); MAX_MSG the largest message; and SLOTS how many
+ * messages can be partway in at once (e.g. with one sender, 1, unless frames may come out of order).
+ * Takes SLOTS * MAX_MSG bytes for the messages in progress.
+ * Call `poll` regularly, which is also when timeouts are checked.
+ */
+pub struct Reassembler<DEC, const FRAME_SIZE: usize, const MAX_MSG: usize, const SLOTS: usize> {
+  decoder: DEC,
+  timeout: Duration,
+  slots: Vec<Slot<MAX_MSG>, SLOTS>,
+  recent: Deque<u8, SLOTS>, // Ids of the last few messages delivered, to spot late duplicates
+  rx_frame: Vec<u8, FRAME_SIZE>,
+  stats: FragmentStats,
+}
+
+impl <DEC: DecoderT, const FRAME_SIZE: usize, const MAX_MSG: usize, const SLOTS: usize> Reassembler<DEC, FRAME_SIZE, MAX_MSG, SLOTS> {
+  /// Gives up on a message after a second without any of its fragments; see `with_timeout`.
+  pub fn new(decoder: DEC) -> Self {
+    const { assert!(SLOTS > 0) };
+    const { assert!(MAX_MSG <= u16::MAX as usize + FRAME_SIZE, "MAX_MSG is bigger than any message can be") };
+
+    #[cfg(not(feature = "std"))]
+    let timeout = Duration::millis(1000);
+
+    #[cfg(feature = "std")]
+    let timeout = Duration::from_millis(1000);
+
+    return Reassembler {
+      decoder: decoder,
+      timeout: timeout,
+      slots: Vec::new(),
+      recent: Deque::new(),
+      rx_frame: Vec::new(),
+      stats: FragmentStats::default(),
+    };
+  }
+
+  /**
+   * How long to wait for the next fragment of a message before giving up on it.
+   */
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    return self;
+  }
+
+  pub fn stats(&self) -> FragmentStats {
+    return self.stats;
+  }
+
+  /// Number of messages partway in.
+  pub fn in_progress(&self) -> usize {
+    return self.slots.len();
+  }
+
+  pub fn decoder(&self) -> &DEC {
+    return &self.decoder;
+  }
+
+  pub fn decoder_mut(&mut self) -> &mut DEC {
+    return &mut self.decoder;
+  }
+
+  /**
+   * Gives up on messages that have timed out, then reads fragments until a message is complete
+   * (into `buffer`) or the Decoder runs dry (WouldBlock).  Only the transport's own errors come back;
+   * anything wrong with a frame just counts in `stats`.
+   */
+  pub fn poll<const CAPACITY: usize>(
+    &mut self,
+    buffer: &mut Vec<u8, CAPACITY>,
+    #[cfg(not(feature = "std"))]
+    now: Instant,
+  ) -> Result<(), nb::Error<DEC::Error>> {
+    #[cfg(feature = "std")]
+    let now = Instant::now();
+
+    return self.poll_at(buffer, now);
+  }
+
+  /**
+   * As `poll`, but with the time passed in (as `poll` takes it without `std`) rather than read from the clock.
+   */
+  pub fn poll_at<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>, now: Instant) -> Result<(), nb::Error<DEC::Error>> {
+    const { assert!(CAPACITY >= MAX_MSG, "buffer must hold MAX_MSG") };
+
+    let timeout = self.timeout;
+    let before = self.slots.len();
+    self.slots.retain(|slot| now < slot.last_seen + timeout);
+    self.stats.abandoned += (before - self.slots.len()) as u32;
+
+    loop {
+      match self.decoder.read(&mut self.rx_frame) {
+        Ok(()) => (),
+        Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+        Err(nb::Error::Other(DencoderError::Transport(e))) => return Err(nb::Error::Other(e)),
+        Err(nb::Error::Other(_)) => {
+          self.stats.bad_frames += 1;
+          continue;
+        },
+      };
+      if self.accept(buffer, now) {
+        return Ok(());
+      }
+    }
+  }
+
+  /**
+   * Files away the fragment in `rx_frame`.  If that completes its message, puts it in `buffer` and returns true.
+   */
+  fn accept<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>, now: Instant) -> bool {
+    if self.rx_frame.len() < FRAGMENT_HEADER_BYTES {
+      self.stats.bad_frames += 1;
+      return false;
+    }
+    let (header, part) = self.rx_frame.split_at(FRAGMENT_HEADER_BYTES);
+    let (id, index, count) = (header[0], header[1], header[2]);
+    let offset = u16::from_be_bytes([header[3], header[4]]) as usize;
+    if index >= count || offset + part.len() > MAX_MSG {
+      self.stats.bad_frames += 1;
+      return false;
+    }
+    if self.recent.iter().any(|&r| r == id) {
+      self.stats.duplicates += 1;
+      return false;
+    }
+
+    let i = match self.slots.iter().position(|s| s.id == id) {
+      Some(i) if self.slots[i].count == count => i,
+      found => {
+        // New message.  (A slot with this id but a different count is left over from before the id wrapped.)
+        if let Some(i) = found.or_else(|| if self.slots.is_full() { self.oldest() } else { None }) {
+          self.slots.swap_remove(i);
+          self.stats.abandoned += 1;
+        }
+        let _ = self.slots.push(Slot { // Made room above
+          id: id,
+          count: count,
+          have: [0; 8],
+          received: 0,
+          len: 0,
+          data: Vec::new(),
+          last_seen: now,
+        });
+        self.slots.len() - 1
+      },
+    };
+
+    let slot = &mut self.slots[i];
+    let (word, bit) = (index as usize / 32, 1 << (index % 32));
+    if slot.have[word] & bit != 0 {
+      self.stats.duplicates += 1;
+      return false;
+    }
+    slot.have[word] |= bit;
+    slot.received += 1;
+    slot.last_seen = now;
+    let end = offset + part.len();
+    if slot.data.len() < end {
+      let _ = slot.data.resize(end, 0); // Checked against MAX_MSG above
+    }
+    slot.data[offset..end].copy_from_slice(part);
+    if index == count - 1 {
+      slot.len = end;
+    }
+    if slot.received < count as usize {
+      return false;
+    }
+
+    let slot = self.slots.swap_remove(i);
+    buffer.clear();
+    let _ = buffer.extend_from_slice(&slot.data[..slot.len]); // CAPACITY >= MAX_MSG
+    if self.recent.is_full() {
+      self.recent.pop_front();
+    }
+    let _ = self.recent.push_back(id);
+    return true;
+  }
+
+  /// Index of the slot that's gone longest without a fragment.
+  fn oldest(&self) -> Option<usize> {
+    return self.slots.iter().enumerate().min_by_key(|(_, s)| s.last_seen).map(|(i, _)| i);
+  }
+}
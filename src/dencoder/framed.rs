@@ -0,0 +1,192 @@
+// Blocking dencoder over `std::io`, for the host side: TcpStreams, serial port files, pipes.
+
+use std::io::{self, Read, Write};
+
+use heapless::Vec;
+
+use super::checksum::{Checksum, Sha256Checksum};
+use super::transport::{BufferSource, FrameSink};
+use super::{Decoder, DecoderT, DecoderStats, DencoderError, Encoder, EncoderStats, EncoderT, Endianness, FecStats, Framing, TransmissionStatus};
+
+/// Most bytes FramedReader asks its reader for at once.
+const READ_CHUNK_BYTES: usize = 256;
+
+/// Anything that isn't the stream's own error, as an io::Error of `kind`.
+fn to_io_error(e: DencoderError<io::Error>, kind: io::ErrorKind) -> io::Error {
+  return match e.split_transport() {
+    Ok(DencoderError::Eof) => io::Error::from(io::ErrorKind::UnexpectedEof),
+    Ok(e) => io::Error::new(kind, e),
+    Err(e) => e,
+  };
+}
+
+struct IoSink<W> {
+  inner: W,
+}
+
+impl <W: Write> FrameSink for IoSink<W> {
+  type Error = io::Error;
+
+  fn tx(&mut self, buffers: &[&[u8]]) -> Result<TransmissionStatus, nb::Error<io::Error>> {
+    for b in buffers {
+      self.inner.write_all(b).map_err(nb::Error::Other)?;
+    }
+    return Ok(TransmissionStatus::Complete);
+  }
+}
+
+/**
+ * Writes frames to a `std::io::Write`.  With the defaults, each frame is byte-for-byte what
+ * `Encoder::write_plain` gives.
+ */
+pub struct FramedWriter<const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, W, CK = Sha256Checksum> {
+  encoder: Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, IoSink<W>, CK>,
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, W: Write, CK: Checksum> FramedWriter<LEN_PREFIX_BYTES, CHECKSUM_BYTES, W, CK> {
+  pub fn new(inner: W) -> Self {
+    return FramedWriter {
+      encoder: Encoder::new(IoSink { inner: inner }),
+    };
+  }
+
+  /// See `Encoder::with_framing`.
+  pub fn with_framing(mut self, framing: Framing) -> Self {
+    self.encoder = self.encoder.with_framing(framing);
+    return self;
+  }
+
+  /// See `Encoder::with_endianness`.
+  pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+    self.encoder = self.encoder.with_endianness(endianness);
+    return self;
+  }
+
+  /// See `Encoder::with_fec`.
+  pub fn with_fec(mut self, correctable: usize) -> Self {
+    self.encoder = self.encoder.with_fec(correctable);
+    return self;
+  }
+
+  /// See `Encoder::with_header`.
+  pub fn with_header(mut self, header: bool) -> Self {
+    self.encoder = self.encoder.with_header(header);
+    return self;
+  }
+
+  /// See `Encoder::stats`.
+  pub fn stats(&self) -> EncoderStats {
+    return self.encoder.stats();
+  }
+
+  pub fn inner(&self) -> &W {
+    return &self.encoder.sink().inner;
+  }
+
+  pub fn inner_mut(&mut self) -> &mut W {
+    return &mut self.encoder.sink_mut().inner;
+  }
+
+  pub fn into_inner(self) -> W {
+    return self.encoder.sink.inner;
+  }
+
+  /**
+   * Writes `msg` as one frame, then flushes.  A message too long for LEN_PREFIX_BYTES is InvalidInput.
+   * If the writer fails partway, the rest of that frame is dropped; the receiver will resync.
+   */
+  pub fn write_frame(&mut self, msg: &[u8]) -> io::Result<()> {
+    let r = self.encoder.write(msg);
+    self.encoder.clear();
+    match r {
+      Ok(()) => (),
+      Err(nb::Error::WouldBlock) => return Err(io::Error::from(io::ErrorKind::WouldBlock)), // IoSink never blocks
+      Err(nb::Error::Other(e)) => return Err(to_io_error(e, io::ErrorKind::InvalidInput)),
+    };
+    return self.inner_mut().flush();
+  }
+}
+
+/**
+ * Reads frames from a `std::io::Read`.  BUF_SIZE is as for Decoder (so messages can be up to
+ * BUF_SIZE less the framing), and also bounds how much is read ahead.
+ */
+pub struct FramedReader<const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, R, const BUF_SIZE: usize, CK = Sha256Checksum> {
+  decoder: Decoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, BufferSource<BUF_SIZE>, BUF_SIZE, CK>,
+  inner: R,
+  msg: Vec<u8, BUF_SIZE>,
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, R: Read, const BUF_SIZE: usize, CK: Checksum> FramedReader<LEN_PREFIX_BYTES, CHECKSUM_BYTES, R, BUF_SIZE, CK> {
+  pub fn new(inner: R) -> Self {
+    return FramedReader {
+      decoder: Decoder::new_plain(),
+      inner: inner,
+      msg: Vec::new(),
+    };
+  }
+
+  /// See `Decoder::with_framing`.
+  pub fn with_framing(mut self, framing: Framing) -> Self {
+    self.decoder = self.decoder.with_framing(framing);
+    return self;
+  }
+
+  /// See `Decoder::with_endianness`.
+  pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+    self.decoder = self.decoder.with_endianness(endianness);
+    return self;
+  }
+
+  /// See `Decoder::with_fec`.
+  pub fn with_fec(mut self, correctable: usize) -> Self {
+    self.decoder = self.decoder.with_fec(correctable);
+    return self;
+  }
+
+  pub fn fec_stats(&self) -> FecStats {
+    return self.decoder.fec_stats();
+  }
+
+  /// See `Decoder::stats`.
+  pub fn stats(&self) -> DecoderStats {
+    return self.decoder.stats();
+  }
+
+  pub fn inner(&self) -> &R {
+    return &self.inner;
+  }
+
+  pub fn inner_mut(&mut self) -> &mut R {
+    return &mut self.inner;
+  }
+
+  pub fn into_inner(self) -> R {
+    return self.inner;
+  }
+
+  /**
+   * Blocks until the next message arrives, and returns it.  A frame that fails validation is
+   * InvalidData, wrapping the DencoderError; just call again for the next one.  If the reader
+   * runs out, UnexpectedEof.
+   */
+  pub fn read_frame(&mut self) -> io::Result<std::vec::Vec<u8>> {
+    loop {
+      match self.decoder.read(&mut self.msg) {
+        Ok(()) => return Ok(self.msg.to_vec()),
+        Err(nb::Error::WouldBlock) => (),
+        Err(nb::Error::Other(e)) => return Err(to_io_error(e.cast::<io::Error>(), io::ErrorKind::InvalidData)),
+      };
+      // The Decoder only blocks once it's used up everything pending, so there's room
+      let mut chunk = [0u8; READ_CHUNK_BYTES];
+      let n = chunk.len().min(self.decoder.source().space());
+      let n = match self.inner.read(&mut chunk[..n]) {
+        Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        Ok(n) => n,
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+        Err(e) => return Err(e),
+      };
+      let _ = self.decoder.add(&chunk[..n]);
+    }
+  }
+}
@@ -0,0 +1,281 @@
+// Reliable, in-order delivery on top of a dencoder Encoder/Decoder pair.
+//
+// Every dencoder message carries a 2-byte header, [kind, seq], then the payload:
+// - DATA seq: a message.  Sequence numbers count up by one per message, wrapping at 256.
+// - ACK seq: "I've got everything before seq" (cumulative).
+// - NAK seq: "I've got everything before seq, and then something after it"; the sender resends from seq.
+// Go-back-N: the receiver only accepts the next message in order, and the sender keeps up to WINDOW
+// messages unacknowledged, resending all of them if the oldest times out.
+
+use heapless::{Deque, Vec};
+
+use crate::rate_meter::{Duration, Instant};
+use super::{DecoderT, DencoderError, EncoderT};
+
+const KIND_DATA: u8 = 0;
+const KIND_ACK: u8 = 1;
+const KIND_NAK: u8 = 2;
+
+/// Bytes of each dencoder message taken up by the reliable-link header.
+pub const RELIABLE_HEADER_BYTES: usize = 2;
+
+/// `TE` and `RE` are the transport error types of the Encoder and Decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReliableError<TE, RE> {
+  /// The message is longer than FRAME_SIZE - RELIABLE_HEADER_BYTES.
+  MessageTooLong,
+  /// The incoming message is bigger than the buffer passed to `poll`.  It wasn't acknowledged, so
+  /// it'll come again; pass a bigger buffer.
+  MessageTooBig,
+  /// The Encoder failed.
+  Send(DencoderError<TE>),
+  /// The Decoder's transport failed.  (Frames that just fail validation are dropped and resent.)
+  Receive(RE),
+}
+
+/// The ReliableError for a given Encoder and Decoder.
+pub type LinkError<ENC, DEC> = ReliableError<<ENC as EncoderT>::Error, <DEC as DecoderT>::Error>;
+
+/// Counts since the link was made.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReliableStats {
+  /// DATA frames sent again, after a timeout or NAK.
+  pub retransmissions: u32,
+  /// DATA frames received that we'd already delivered.
+  pub duplicates: u32,
+  /// Frames the Decoder dropped as damaged, or that made no sense (e.g. an ACK for something never sent).
+  pub bad_frames: u32,
+}
+
+struct Pending<const FRAME_SIZE: usize> {
+  frame: Vec<u8, FRAME_SIZE>, // Header included
+  sent_at: Option<Instant>,
+}
+
+/**
+ * Sends and receives messages reliably and in order over an Encoder and Decoder, which may lose,
+ * damage, or duplicate frames (but not reorder them).  The other end needs a ReliableLink too.
+ * FRAME_SIZE is the largest dencoder message, so messages can be up to FRAME_SIZE - RELIABLE_HEADER_BYTES;
+ * the Decoders on both ends must accept that.  WINDOW (under 128) is how many messages can be
+ * in flight before `send` blocks.
+ * Nothing happens on its own; call `poll` regularly, both to receive and to drive sending.
+ */
+pub struct ReliableLink<ENC, DEC, const FRAME_SIZE: usize, const WINDOW: usize> {
+  encoder: ENC,
+  decoder: DEC,
+  timeout: Duration,
+  // Sending
+  window: Deque<Pending<FRAME_SIZE>, WINDOW>, // Unacknowledged, oldest first
+  base_seq: u8, // Seq of window.front()
+  next_unsent: usize, // Index into `window` of the next one to (re)send
+  // Receiving
+  expected_seq: u8,
+  ack_due: Option<u8>, // ACK/NAK kind
+  nak_sent: bool, // Already NAKed expected_seq; don't keep doing it for every frame after the gap
+  // The frame `encoder.write` is partway through, if any; it has to be finished before anything else goes
+  tx_frame: Vec<u8, FRAME_SIZE>,
+  tx_busy: bool,
+  rx_frame: Vec<u8, FRAME_SIZE>,
+  stats: ReliableStats,
+}
+
+impl <ENC: EncoderT, DEC: DecoderT, const FRAME_SIZE: usize, const WINDOW: usize> ReliableLink<ENC, DEC, FRAME_SIZE, WINDOW> {
+  /// Retransmits after 100ms without an ACK; see `with_timeout`.
+  pub fn new(encoder: ENC, decoder: DEC) -> Self {
+    const { assert!(WINDOW > 0 && WINDOW < 128, "WINDOW must be 1..=127, so seqs in and out of the window can't be confused") };
+    const { assert!(FRAME_SIZE >= RELIABLE_HEADER_BYTES) };
+
+    #[cfg(not(feature = "std"))]
+    let timeout = Duration::millis(100);
+
+    #[cfg(feature = "std")]
+    let timeout = Duration::from_millis(100);
+
+    return ReliableLink {
+      encoder: encoder,
+      decoder: decoder,
+      timeout: timeout,
+      window: Deque::new(),
+      base_seq: 0,
+      next_unsent: 0,
+      expected_seq: 0,
+      ack_due: None,
+      nak_sent: false,
+      tx_frame: Vec::new(),
+      tx_busy: false,
+      rx_frame: Vec::new(),
+      stats: ReliableStats::default(),
+    };
+  }
+
+  /**
+   * How long to wait for an ACK before resending.  Should comfortably exceed the round trip,
+   * including however long the other end may go between `poll`s.
+   */
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    return self;
+  }
+
+  pub fn stats(&self) -> ReliableStats {
+    return self.stats;
+  }
+
+  /// Number of messages sent (or queued) but not yet acknowledged.
+  pub fn unacked(&self) -> usize {
+    return self.window.len();
+  }
+
+  /**
+   * Queues `msg` to go out on the next `poll`.  Returns WouldBlock if WINDOW messages are already
+   * waiting on ACKs; `poll` until some come in, then try again.
+   */
+  pub fn send(&mut self, msg: &[u8]) -> Result<(), nb::Error<LinkError<ENC, DEC>>> {
+    if msg.len() > FRAME_SIZE - RELIABLE_HEADER_BYTES {
+      return Err(nb::Error::Other(ReliableError::MessageTooLong));
+    }
+    if self.window.is_full() {
+      return Err(nb::Error::WouldBlock);
+    }
+    let seq = self.base_seq.wrapping_add(self.window.len() as u8);
+    let mut frame = Vec::new();
+    let _ = frame.extend_from_slice(&[KIND_DATA, seq]); // Checked the length above
+    let _ = frame.extend_from_slice(msg);
+    let _ = self.window.push_back(Pending { frame, sent_at: None });
+    return Ok(());
+  }
+
+  /**
+   * Does everything there is to do: takes in whatever the Decoder has (handing back the next
+   * message, if one's arrived, in `buffer`), resends anything that's timed out, and sends what it can.
+   * Returns WouldBlock if there's no message yet.
+   */
+  pub fn poll<const CAPACITY: usize>(
+    &mut self,
+    buffer: &mut Vec<u8, CAPACITY>,
+    #[cfg(not(feature = "std"))]
+    now: Instant,
+  ) -> Result<(), nb::Error<LinkError<ENC, DEC>>> {
+    #[cfg(feature = "std")]
+    let now = Instant::now();
+
+    return self.poll_at(buffer, now);
+  }
+
+  /**
+   * As `poll`, but at `now` rather than `Instant::now()`, so timeouts can run on a simulated clock.
+   */
+  pub fn poll_at<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>, now: Instant) -> Result<(), nb::Error<LinkError<ENC, DEC>>> {
+    let received = self.receive(buffer).map_err(nb::Error::Other)?;
+
+    if let Some(oldest) = self.window.front()
+      && let Some(sent_at) = oldest.sent_at
+      && now >= sent_at + self.timeout {
+      // Go back N
+      self.stats.retransmissions += self.next_unsent as u32;
+      self.next_unsent = 0;
+    }
+
+    match self.transmit(now) {
+      Ok(()) | Err(nb::Error::WouldBlock) => (),
+      Err(nb::Error::Other(e)) => return Err(nb::Error::Other(e)),
+    };
+    return if received { Ok(()) } else { Err(nb::Error::WouldBlock) };
+  }
+
+  /**
+   * Reads frames until one delivers a message into `buffer` (true) or the Decoder runs dry (false).
+   */
+  fn receive<const CAPACITY: usize>(&mut self, buffer: &mut Vec<u8, CAPACITY>) -> Result<bool, LinkError<ENC, DEC>> {
+    loop {
+      match self.decoder.read(&mut self.rx_frame) {
+        Ok(()) => (),
+        Err(nb::Error::WouldBlock) => return Ok(false),
+        Err(nb::Error::Other(DencoderError::Transport(e))) => return Err(ReliableError::Receive(e)),
+        Err(nb::Error::Other(_)) => {
+          // Damaged; a NAK would be nice, but we can't trust anything in it.  The sender will time out.
+          self.stats.bad_frames += 1;
+          continue;
+        },
+      };
+      if self.rx_frame.len() < RELIABLE_HEADER_BYTES {
+        self.stats.bad_frames += 1;
+        continue;
+      }
+      let (kind, seq) = (self.rx_frame[0], self.rx_frame[1]);
+      match kind {
+        KIND_DATA => {
+          if seq == self.expected_seq {
+            buffer.clear();
+            if buffer.extend_from_slice(&self.rx_frame[RELIABLE_HEADER_BYTES..]).is_err() {
+              // Can't deliver it, so don't ACK it; stuck until the caller passes a bigger buffer
+              return Err(ReliableError::MessageTooBig);
+            }
+            self.expected_seq = self.expected_seq.wrapping_add(1);
+            self.nak_sent = false;
+            self.ack_due = Some(KIND_ACK);
+            return Ok(true);
+          }
+          if seq.wrapping_sub(self.expected_seq) < 128 {
+            // Ahead of what we expected, so something got lost
+            if !self.nak_sent {
+              self.nak_sent = true;
+              self.ack_due = Some(KIND_NAK);
+            }
+          } else {
+            // Already have it; our ACK must have been lost
+            self.stats.duplicates += 1;
+            self.ack_due = Some(KIND_ACK);
+          }
+        },
+        KIND_ACK | KIND_NAK => {
+          let acked = seq.wrapping_sub(self.base_seq) as usize;
+          if acked > self.window.len() {
+            self.stats.bad_frames += 1;
+            continue;
+          }
+          for _ in 0..acked {
+            self.window.pop_front();
+          }
+          self.base_seq = seq;
+          self.next_unsent = self.next_unsent.saturating_sub(acked);
+          if kind == KIND_NAK && self.next_unsent > 0 {
+            self.stats.retransmissions += self.next_unsent as u32;
+            self.next_unsent = 0;
+          }
+        },
+        _ => self.stats.bad_frames += 1,
+      };
+    }
+  }
+
+  /**
+   * Writes frames until there's nothing left to send or the Encoder blocks.  ACKs go first.
+   */
+  fn transmit(&mut self, now: Instant) -> Result<(), nb::Error<LinkError<ENC, DEC>>> {
+    loop {
+      if self.tx_busy {
+        let r = self.encoder.write(&self.tx_frame);
+        match r {
+          Ok(()) => self.tx_busy = false,
+          Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+          Err(nb::Error::Other(e)) => {
+            self.tx_busy = false;
+            return Err(nb::Error::Other(ReliableError::Send(e)));
+          },
+        };
+      }
+      self.tx_frame.clear();
+      if let Some(kind) = self.ack_due.take() {
+        let _ = self.tx_frame.extend_from_slice(&[kind, self.expected_seq]);
+      } else if let Some(pending) = self.window.get_mut(self.next_unsent) {
+        let _ = self.tx_frame.extend_from_slice(&pending.frame);
+        pending.sent_at = Some(now);
+        self.next_unsent += 1;
+      } else {
+        return Ok(());
+      }
+      self.tx_busy = true;
+    }
+  }
+}
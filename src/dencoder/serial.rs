@@ -0,0 +1,112 @@
+// FrameSink and FrameSource over `embedded_io` serial ports (UARTs, USB CDC, etc.).
+
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+
+use super::transport::{FrameSink, FrameSource};
+use super::TransmissionStatus;
+
+/**
+ * Sends frames out an `embedded_io::Write`.  Writes block until the whole frame part is taken, as
+ * `Write` does; the Encoder never sees WouldBlock.  Doesn't flush; do that via `inner_mut` if
+ * you need the frame to be physically out (e.g. before turning an RS-485 transceiver around).
+ */
+pub struct SerialSink<W> {
+  inner: W,
+}
+
+impl <W: Write> SerialSink<W> {
+  pub fn new(inner: W) -> SerialSink<W> {
+    return SerialSink {
+      inner: inner,
+    };
+  }
+
+  pub fn inner(&self) -> &W {
+    return &self.inner;
+  }
+
+  pub fn inner_mut(&mut self) -> &mut W {
+    return &mut self.inner;
+  }
+
+  pub fn into_inner(self) -> W {
+    return self.inner;
+  }
+}
+
+impl <W: Write> FrameSink for SerialSink<W> {
+  type Error = W::Error;
+
+  fn tx(&mut self, buffers: &[&[u8]]) -> Result<TransmissionStatus, nb::Error<W::Error>> {
+    for b in buffers {
+      self.inner.write_all(b).map_err(nb::Error::Other)?;
+    }
+    return Ok(TransmissionStatus::Complete);
+  }
+}
+
+/**
+ * Reads frames from an `embedded_io::Read`, without blocking: it only reads while `read_ready`
+ * says there's something there, and otherwise returns what it got (or WouldBlock).
+ * A reader at EOF reads as nothing arriving.  An error after some bytes have come in is held
+ * back until the next call, so those bytes aren't lost.
+ */
+pub struct SerialSource<R: ErrorType> {
+  inner: R,
+  error: Option<R::Error>, // Hit after a partial read; reported next time
+}
+
+impl <R: Read + ReadReady> SerialSource<R> {
+  pub fn new(inner: R) -> SerialSource<R> {
+    return SerialSource {
+      inner: inner,
+      error: None,
+    };
+  }
+
+  pub fn inner(&self) -> &R {
+    return &self.inner;
+  }
+
+  pub fn inner_mut(&mut self) -> &mut R {
+    return &mut self.inner;
+  }
+
+  pub fn into_inner(self) -> R {
+    return self.inner;
+  }
+}
+
+impl <R: Read + ReadReady> FrameSource for SerialSource<R> {
+  type Error = R::Error;
+
+  fn rx(&mut self, buffer: &mut [u8]) -> Result<TransmissionStatus, nb::Error<R::Error>> {
+    if let Some(e) = self.error.take() {
+      return Err(nb::Error::Other(e));
+    }
+    let mut filled = 0;
+    while filled < buffer.len() {
+      let r = match self.inner.read_ready() {
+        Ok(true) => self.inner.read(&mut buffer[filled..]),
+        Ok(false) => break,
+        Err(e) => Err(e),
+      };
+      match r {
+        Ok(0) => break, // EOF
+        Ok(n) => filled += n,
+        Err(e) if filled == 0 => return Err(nb::Error::Other(e)),
+        Err(e) => {
+          self.error = Some(e);
+          break;
+        },
+      };
+    }
+    if filled == buffer.len() {
+      return Ok(TransmissionStatus::Complete);
+    }
+    if filled == 0 {
+      return Err(nb::Error::WouldBlock);
+    }
+    return Ok(TransmissionStatus::Partial(filled));
+  }
+}
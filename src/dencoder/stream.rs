@@ -0,0 +1,185 @@
+// Frames whose message is handed over a chunk at a time, for messages too big to hold in memory.
+
+use super::checksum::{Checksum, MAX_CHECKSUM_BYTES};
+use super::transport::FrameSink;
+use super::{fec, CobsRun, DencoderError, Encoder, FrameHead, Framing};
+
+/**
+ * A frame being written through an Encoder a chunk at a time; from `Encoder::begin`.  Call
+ * `write_chunk` until you've given it the whole message, then `finish`.  What goes to the sink is
+ * byte-for-byte what `write` would send for the chunks concatenated.
+ *
+ * Either call may return WouldBlock, as `write` does; call it again with the same chunk to resume.
+ * If the sink fails, the frame is abandoned and later calls return `DencoderError::BadStream`.
+ * Dropping it before `finish` abandons the frame too, as `Encoder::clear`.
+ */
+pub struct StreamedFrame<'e, const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SINK: FrameSink, CK: Checksum> {
+  encoder: &'e mut Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SINK, CK>,
+  head: FrameHead<LEN_PREFIX_BYTES>,
+  len: usize,
+  written: usize, // Message bytes taken so far
+  checksum: CK, // Over those
+  msg_hash: Option<[u8; MAX_CHECKSUM_BYTES]>, // Once `finish` has worked it out
+  generator: [u8; fec::MAX_PARITY_BYTES + 1],
+  // Where the frame had got to when the last call returned Ok; a call that blocks starts over from here
+  head_sent: bool, // Handed to `send`/`send_cobs`, though with COBS it may still be sitting in `run`
+  offset: usize,
+  run: CobsRun,
+  block_fill: usize, // Body bytes in the current FEC block
+  parity: [u8; fec::MAX_PARITY_BYTES], // Of those
+  started: bool, // `before_tx` has been called
+  done: bool, // `after_tx` has been called
+}
+
+impl <'e, const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SINK: FrameSink, CK: Checksum> StreamedFrame<'e, LEN_PREFIX_BYTES, CHECKSUM_BYTES, SINK, CK> {
+  pub(super) fn new(encoder: &'e mut Encoder<LEN_PREFIX_BYTES, CHECKSUM_BYTES, SINK, CK>, head: FrameHead<LEN_PREFIX_BYTES>, len: usize) -> Self {
+    let generator = if encoder.fec_parity > 0 { fec::generator(encoder.fec_parity) } else { [0; fec::MAX_PARITY_BYTES + 1] };
+    return StreamedFrame {
+      encoder: encoder,
+      head: head,
+      len: len,
+      written: 0,
+      checksum: CK::default(),
+      msg_hash: None,
+      generator: generator,
+      head_sent: false,
+      offset: 0,
+      run: CobsRun { bytes: [0; 254], len: 0 },
+      block_fill: 0,
+      parity: [0; fec::MAX_PARITY_BYTES],
+      started: false,
+      done: false,
+    };
+  }
+
+  /// The Encoder's sink, e.g. to drain a VecSink between chunks.
+  pub fn sink_mut(&mut self) -> &mut SINK {
+    return &mut self.encoder.sink;
+  }
+
+  /// Message bytes still to come, of the `len` given to `begin`.
+  pub fn remaining(&self) -> usize {
+    return self.len - self.written;
+  }
+
+  /**
+   * Sends the next `chunk` of the message (and the start of the frame, the first time).  Err(BadStream)
+   * if that's more than `remaining`.
+   */
+  pub fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), nb::Error<DencoderError<SINK::Error>>> {
+    if self.done || chunk.len() > self.remaining() {
+      return Err(nb::Error::Other(DencoderError::BadStream));
+    }
+    self.start();
+    self.emit(chunk, false).map_err(|e| self.fail(e))?;
+    self.checksum.update(chunk);
+    self.written += chunk.len();
+    return Ok(());
+  }
+
+  /**
+   * Sends the checksum and whatever else ends the frame.  Err(BadStream) if the chunks didn't add up
+   * to the `len` given to `begin`.
+   */
+  pub fn finish(&mut self) -> Result<(), nb::Error<DencoderError<SINK::Error>>> {
+    if self.done || self.written != self.len {
+      return Err(nb::Error::Other(DencoderError::BadStream));
+    }
+    self.start();
+    let checksum = &mut self.checksum;
+    let msg_hash = *self.msg_hash.get_or_insert_with(|| core::mem::take(checksum).finalize());
+    self.emit(&msg_hash[0..CHECKSUM_BYTES], true).map_err(|e| self.fail(e))?;
+    self.end();
+    self.encoder.count_frame();
+    return Ok(());
+  }
+
+  fn start(&mut self) {
+    if !self.started {
+      self.started = true;
+      self.encoder.sink.before_tx();
+    }
+  }
+
+  fn end(&mut self) {
+    self.done = true;
+    self.encoder.progress = 0;
+    if self.started {
+      self.encoder.sink.after_tx();
+    }
+  }
+
+  fn fail(&mut self, e: nb::Error<SINK::Error>) -> nb::Error<DencoderError<SINK::Error>> {
+    return match e {
+      nb::Error::WouldBlock => nb::Error::WouldBlock,
+      nb::Error::Other(e) => {
+        self.end();
+        self.encoder.stats.transport_errors += 1;
+        nb::Error::Other(DencoderError::Transport(e))
+      },
+    };
+  }
+
+  /**
+   * Sends the head if it hasn't gone yet, then `data` as the next part of the body, then (if `last`)
+   * the final parity and COBS delimiter.  Works from copies of the state, and only keeps them if it
+   * all gets out, so a retry after WouldBlock produces the same bytes (and `Encoder::send` skips the
+   * ones already sent).
+   */
+  fn emit(&mut self, data: &[u8], last: bool) -> Result<(), nb::Error<SINK::Error>> {
+    let encoder = &mut *self.encoder;
+    let framing = encoder.framing;
+    let fec_parity = encoder.fec_parity;
+    let mut head_sent = self.head_sent;
+    let mut offset = self.offset;
+    let mut run = self.run;
+    let mut block_fill = self.block_fill;
+    let mut parity = self.parity;
+    let mut send = |pieces: &mut [&[u8]]| match framing {
+      Framing::MagicByte => encoder.send(&mut offset, pieces),
+      Framing::Cobs => encoder.send_cobs(&mut offset, &mut run, pieces),
+    };
+    if !head_sent {
+      send(&mut self.head.pieces())?;
+      head_sent = true;
+    }
+    if fec_parity == 0 {
+      send(&mut [data])?;
+    } else {
+      let block_data = fec::BLOCK_BYTES - fec_parity;
+      let mut rest = data;
+      while !rest.is_empty() {
+        let n = rest.len().min(block_data - block_fill);
+        fec::update_parity(&self.generator, &rest[..n], &mut parity[..fec_parity]);
+        send(&mut [&rest[..n]])?;
+        block_fill += n;
+        rest = &rest[n..];
+        if block_fill == block_data {
+          send(&mut [&parity[..fec_parity]])?;
+          parity = [0; fec::MAX_PARITY_BYTES];
+          block_fill = 0;
+        }
+      }
+      if last && block_fill > 0 {
+        send(&mut [&parity[..fec_parity]])?;
+      }
+    }
+    if last && framing == Framing::Cobs {
+      encoder.finish_cobs(&mut offset, &mut run)?;
+    }
+    self.head_sent = head_sent;
+    self.offset = offset;
+    self.run = run;
+    self.block_fill = block_fill;
+    self.parity = parity;
+    return Ok(());
+  }
+}
+
+impl <const LEN_PREFIX_BYTES: usize, const CHECKSUM_BYTES: usize, SINK: FrameSink, CK: Checksum> Drop for StreamedFrame<'_, LEN_PREFIX_BYTES, CHECKSUM_BYTES, SINK, CK> {
+  fn drop(&mut self) {
+    if !self.done {
+      self.end();
+    }
+  }
+}
@@ -0,0 +1,303 @@
+// Where an Encoder's bytes go, and where a Decoder's come from.
+
+use core::convert::Infallible;
+
+use heapless::{CapacityError, Vec};
+
+use super::TransmissionStatus;
+
+/**
+ * The Encoder's output.  Closures `FnMut(&[&[u8]]) -> Result<TransmissionStatus, nb::Error<E>>`
+ * are sinks too (annotate the argument type, or inference won't manage it); implement the trait
+ * yourself to wrap e.g. a HAL UART and get at the hooks.
+ */
+pub trait FrameSink {
+  type Error;
+
+  /// Called before the first byte of each frame.
+  fn before_tx(&mut self) {
+  }
+
+  /**
+   * Takes the next part of the frame, as `buffers` concatenated.  Returns Complete if it took all
+   * of it; Partial(n) if it only took the first n bytes (across all the buffers), or
+   * Err(WouldBlock) if none.  The Encoder will come back with the rest.
+   */
+  fn tx(&mut self, buffers: &[&[u8]]) -> Result<TransmissionStatus, nb::Error<Self::Error>>;
+
+  /// Called once the frame is fully out (or `tx` failed).
+  fn after_tx(&mut self) {
+  }
+}
+
+/**
+ * The Decoder's input.  Closures `FnMut(&mut [u8]) -> Result<TransmissionStatus, nb::Error<E>>`
+ * are sources too.
+ */
+pub trait FrameSource {
+  type Error;
+
+  /// Called at the start of every `read`.
+  fn before_rx(&mut self) {
+  }
+
+  /**
+   * Fills `buffer`, returning Complete; or as much of it as there is, returning Partial(n), or
+   * Err(WouldBlock) if there's nothing yet.  `buffer` is exactly as long as the Decoder wants,
+   * so don't worry about reading too far.
+   */
+  fn rx(&mut self, buffer: &mut [u8]) -> Result<TransmissionStatus, nb::Error<Self::Error>>;
+
+  /// Called after each message is successfully read.
+  fn after_rx(&mut self) {
+  }
+}
+
+impl <F, E> FrameSink for F where F: FnMut(&[&[u8]]) -> Result<TransmissionStatus, nb::Error<E>> {
+  type Error = E;
+
+  fn tx(&mut self, buffers: &[&[u8]]) -> Result<TransmissionStatus, nb::Error<E>> {
+    return self(buffers);
+  }
+}
+
+impl <F, E> FrameSource for F where F: FnMut(&mut [u8]) -> Result<TransmissionStatus, nb::Error<E>> {
+  type Error = E;
+
+  fn rx(&mut self, buffer: &mut [u8]) -> Result<TransmissionStatus, nb::Error<E>> {
+    return self(buffer);
+  }
+}
+
+/// FnSink's `tx` callback.
+pub type TxFn<STATE, E> = fn(&mut STATE, &[&[u8]]) -> Result<TransmissionStatus, nb::Error<E>>;
+/// FnSource's `rx` callback.
+pub type RxFn<STATE, E> = fn(&mut STATE, &mut [u8]) -> Result<TransmissionStatus, nb::Error<E>>;
+
+/**
+ * The old-style `fn` pointer callbacks, all sharing `state`.
+ */
+pub struct FnSink<STATE, E> {
+  pub state: STATE,
+  before_tx: Option<fn(&mut STATE)>,
+  tx: TxFn<STATE, E>,
+  after_tx: Option<fn(&mut STATE)>,
+}
+
+impl <STATE, E> FnSink<STATE, E> {
+  pub fn new(state: STATE, before_tx: Option<fn(&mut STATE)>, tx: TxFn<STATE, E>, after_tx: Option<fn(&mut STATE)>) -> FnSink<STATE, E> {
+    return FnSink {
+      state: state,
+      before_tx: before_tx,
+      tx: tx,
+      after_tx: after_tx,
+    };
+  }
+}
+
+impl <STATE, E> FrameSink for FnSink<STATE, E> {
+  type Error = E;
+
+  fn before_tx(&mut self) {
+    if let Some(f) = self.before_tx {
+      f(&mut self.state);
+    }
+  }
+
+  fn tx(&mut self, buffers: &[&[u8]]) -> Result<TransmissionStatus, nb::Error<E>> {
+    return (self.tx)(&mut self.state, buffers);
+  }
+
+  fn after_tx(&mut self) {
+    if let Some(f) = self.after_tx {
+      f(&mut self.state);
+    }
+  }
+}
+
+/// See FnSink
+pub struct FnSource<STATE, E> {
+  pub state: STATE,
+  before_rx: Option<fn(&mut STATE)>,
+  rx: RxFn<STATE, E>,
+  after_rx: Option<fn(&mut STATE)>,
+}
+
+impl <STATE, E> FnSource<STATE, E> {
+  pub fn new(state: STATE, before_rx: Option<fn(&mut STATE)>, rx: RxFn<STATE, E>, after_rx: Option<fn(&mut STATE)>) -> FnSource<STATE, E> {
+    return FnSource {
+      state: state,
+      before_rx: before_rx,
+      rx: rx,
+      after_rx: after_rx,
+    };
+  }
+}
+
+impl <STATE, E> FrameSource for FnSource<STATE, E> {
+  type Error = E;
+
+  fn before_rx(&mut self) {
+    if let Some(f) = self.before_rx {
+      f(&mut self.state);
+    }
+  }
+
+  fn rx(&mut self, buffer: &mut [u8]) -> Result<TransmissionStatus, nb::Error<E>> {
+    return (self.rx)(&mut self.state, buffer);
+  }
+
+  fn after_rx(&mut self) {
+    if let Some(f) = self.after_rx {
+      f(&mut self.state);
+    }
+  }
+}
+
+/**
+ * Writes frames into a slice, one after another.  Once it's full, `tx` takes what fits and
+ * returns Partial.
+ */
+pub struct SliceSink<'a> {
+  out: &'a mut [u8],
+  written: usize,
+}
+
+impl <'a> SliceSink<'a> {
+  pub fn new(out: &'a mut [u8]) -> SliceSink<'a> {
+    return SliceSink {
+      out: out,
+      written: 0,
+    };
+  }
+
+  /// Number of bytes written into `out` so far.
+  pub fn written(&self) -> usize {
+    return self.written;
+  }
+}
+
+impl FrameSink for SliceSink<'_> {
+  type Error = Infallible;
+
+  fn tx(&mut self, buffers: &[&[u8]]) -> Result<TransmissionStatus, nb::Error<Infallible>> {
+    let mut taken = 0;
+    for b in buffers {
+      let n = b.len().min(self.out.len() - self.written);
+      self.out[self.written..self.written + n].copy_from_slice(&b[..n]);
+      self.written += n;
+      taken += n;
+      if n < b.len() {
+        return Ok(TransmissionStatus::Partial(taken));
+      }
+    }
+    return Ok(TransmissionStatus::Complete);
+  }
+}
+
+/**
+ * Collects frame bytes in a buffer of N, for you to pass on yourself.  Once it's full, `tx` takes
+ * what fits and returns Partial; `take` what's there and `write` again to carry on with the frame.
+ */
+pub struct VecSink<const N: usize> {
+  out: Vec<u8, N>,
+}
+
+impl <const N: usize> VecSink<N> {
+  pub fn new() -> VecSink<N> {
+    return VecSink {
+      out: Vec::new(),
+    };
+  }
+
+  pub fn as_slice(&self) -> &[u8] {
+    return &self.out;
+  }
+
+  /**
+   * Removes everything written so far (clearing the buffer) and returns it.
+   */
+  pub fn take(&mut self) -> Vec<u8, N> {
+    return core::mem::take(&mut self.out);
+  }
+}
+
+impl <const N: usize> Default for VecSink<N> {
+  fn default() -> Self {
+    return VecSink::new();
+  }
+}
+
+impl <const N: usize> FrameSink for VecSink<N> {
+  type Error = Infallible;
+
+  fn tx(&mut self, buffers: &[&[u8]]) -> Result<TransmissionStatus, nb::Error<Infallible>> {
+    let mut taken = 0;
+    for b in buffers {
+      let n = b.len().min(N - self.out.len());
+      let _ = self.out.extend_from_slice(&b[..n]); // Fits
+      taken += n;
+      if n < b.len() {
+        return Ok(TransmissionStatus::Partial(taken));
+      }
+    }
+    return Ok(TransmissionStatus::Complete);
+  }
+}
+
+/**
+ * Bytes waiting for a Decoder, which you `add` to by hand.
+ */
+pub struct BufferSource<const N: usize> {
+  pending: Vec<u8, N>,
+}
+
+impl <const N: usize> BufferSource<N> {
+  pub fn new() -> BufferSource<N> {
+    return BufferSource {
+      pending: Vec::new(),
+    };
+  }
+
+  /**
+   * Copies `input` onto the pending buffer.  Returns error if out of space.
+   */
+  pub fn add(&mut self, input: &[u8]) -> Result<(), CapacityError> {
+    return self.pending.extend_from_slice(input);
+  }
+
+  /// Bytes that can be added before it's full.
+  pub fn space(&self) -> usize {
+    return N - self.pending.len();
+  }
+
+  /**
+   * Removes pending input from the buffer (clearing it) and returns it.
+   */
+  pub fn take(&mut self) -> Vec<u8, N> {
+    return core::mem::take(&mut self.pending);
+  }
+}
+
+impl <const N: usize> Default for BufferSource<N> {
+  fn default() -> Self {
+    return BufferSource::new();
+  }
+}
+
+impl <const N: usize> FrameSource for BufferSource<N> {
+  type Error = Infallible;
+
+  fn rx(&mut self, buffer: &mut [u8]) -> Result<TransmissionStatus, nb::Error<Infallible>> {
+    let n = self.pending.len().min(buffer.len());
+    if n == 0 {
+      return Err(nb::Error::WouldBlock);
+    }
+    buffer[..n].copy_from_slice(&self.pending[..n]);
+    self.pending.drain(0..n); //LEAK I wonder if there's a more efficient way?
+    if n < buffer.len() {
+      return Ok(TransmissionStatus::Partial(n));
+    }
+    return Ok(TransmissionStatus::Complete);
+  }
+}
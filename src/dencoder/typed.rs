@@ -0,0 +1,114 @@
+// Typed messages: values serialized with postcard, one per dencoder message.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use heapless::Vec;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{DecoderT, DencoderError, EncoderT};
+
+/// `E` is the error type of the Encoder's or Decoder's transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypedError<E> {
+  /// The value couldn't be serialized; most likely it doesn't fit in BUF_SIZE.  Nothing was sent.
+  Serialize(postcard::Error),
+  /// The frame arrived intact, but isn't a valid T.  The sender's T probably differs from ours.
+  Deserialize(postcard::Error),
+  /// From the Encoder or Decoder, e.g. a checksum failure.
+  Dencoder(DencoderError<E>),
+}
+
+impl <E: fmt::Display> fmt::Display for TypedError<E> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    return match self {
+      TypedError::Serialize(e) => write!(f, "serialize failed: {}", e),
+      TypedError::Deserialize(e) => write!(f, "deserialize failed: {}", e),
+      TypedError::Dencoder(e) => write!(f, "{}", e),
+    };
+  }
+}
+
+#[cfg(feature = "std")]
+impl <E: fmt::Debug + fmt::Display> std::error::Error for TypedError<E> {}
+
+/**
+ * Sends Ts over an Encoder, each serialized with postcard into a buffer of BUF_SIZE and sent as one message.
+ */
+pub struct TypedEncoder<ENC, T, const BUF_SIZE: usize> {
+  encoder: ENC,
+  buffer: [u8; BUF_SIZE],
+  value: PhantomData<fn(&T)>,
+}
+
+impl <ENC: EncoderT, T: Serialize, const BUF_SIZE: usize> TypedEncoder<ENC, T, BUF_SIZE> {
+  pub fn new(encoder: ENC) -> Self {
+    return TypedEncoder {
+      encoder: encoder,
+      buffer: [0; BUF_SIZE],
+      value: PhantomData,
+    };
+  }
+
+  pub fn encoder(&self) -> &ENC {
+    return &self.encoder;
+  }
+
+  pub fn encoder_mut(&mut self) -> &mut ENC {
+    return &mut self.encoder;
+  }
+
+  pub fn into_inner(self) -> ENC {
+    return self.encoder;
+  }
+
+  /**
+   * Serializes `value` and writes it.  Returns WouldBlock if the Encoder does; call again with the
+   * same `value` to carry on.
+   */
+  pub fn send(&mut self, value: &T) -> Result<(), nb::Error<TypedError<ENC::Error>>> {
+    let msg = postcard::to_slice(value, &mut self.buffer).map_err(|e| nb::Error::Other(TypedError::Serialize(e)))?;
+    return self.encoder.write(msg).map_err(|e| e.map(TypedError::Dencoder));
+  }
+}
+
+/**
+ * Receives Ts from a Decoder, each a postcard-serialized message of up to BUF_SIZE bytes.
+ */
+pub struct TypedDecoder<DEC, T, const BUF_SIZE: usize> {
+  decoder: DEC,
+  buffer: Vec<u8, BUF_SIZE>,
+  value: PhantomData<fn() -> T>,
+}
+
+impl <DEC: DecoderT, T: DeserializeOwned, const BUF_SIZE: usize> TypedDecoder<DEC, T, BUF_SIZE> {
+  pub fn new(decoder: DEC) -> Self {
+    return TypedDecoder {
+      decoder: decoder,
+      buffer: Vec::new(),
+      value: PhantomData,
+    };
+  }
+
+  pub fn decoder(&self) -> &DEC {
+    return &self.decoder;
+  }
+
+  pub fn decoder_mut(&mut self) -> &mut DEC {
+    return &mut self.decoder;
+  }
+
+  pub fn into_inner(self) -> DEC {
+    return self.decoder;
+  }
+
+  /**
+   * Reads the next message and deserializes it.  Either can fail without affecting later messages;
+   * just call again.  Returns WouldBlock if there isn't a whole message yet.
+   */
+  pub fn receive(&mut self) -> Result<T, nb::Error<TypedError<DEC::Error>>> {
+    self.decoder.read(&mut self.buffer).map_err(|e| e.map(TypedError::Dencoder))?;
+    return postcard::from_bytes(&self.buffer).map_err(|e| nb::Error::Other(TypedError::Deserialize(e)));
+  }
+}
@@ -4,14 +4,15 @@
 #[cfg(not(feature = "std"))]
 use core::option::{Option, Option::None, Option::Some};
 
+// The clock here and in the dencoder's timeouts
 #[cfg(feature = "std")]
-use std::time::{Instant, Duration};
+pub(crate) use std::time::{Instant, Duration};
 
 // From esp_hal
 #[cfg(not(feature = "std"))]
-type Instant = fugit::Instant<u64, 1, 1_000_000>;
+pub(crate) type Instant = fugit::Instant<u64, 1, 1_000_000>;
 #[cfg(not(feature = "std"))]
-type Duration = fugit::Duration<u64, 1, 1_000_000>;
+pub(crate) type Duration = fugit::Duration<u64, 1, 1_000_000>;
 
 pub struct RateMeter {
   pub count: u64,
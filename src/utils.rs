@@ -13,8 +13,10 @@ pub fn convert_to_lossy_utf8(input: &mut [u8]) {
 pub fn to_hex_string(data: &[u8]) -> String<1024> {
   let mut hex_string = String::<1024>::new(); // Create a heapless String with a max capacity of 64
   for byte in data {
-    // Write the hex representation of each byte
-    write!(&mut hex_string, "{:02x}", byte).unwrap();
+    // Write the hex representation of each byte; past 512 bytes, there's no room, so it's cut short
+    if write!(&mut hex_string, "{:02x}", byte).is_err() {
+      break;
+    }
   }
   hex_string
 }
\ No newline at end of file
@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+use embedded_io_async::{ErrorType, Read, Write};
+use erhannis_misc::dencoder::asynch::{AsyncDecoder, AsyncEncoder};
+use erhannis_misc::dencoder::{DencoderError, Framing};
+use heapless::Vec;
+
+/// An in-memory serial line that moves at most `max_chunk` bytes per read or write.
+struct Pipe {
+  bytes: VecDeque<u8>,
+  max_chunk: usize,
+}
+
+impl ErrorType for Pipe {
+  type Error = Infallible;
+}
+
+impl Read for Pipe {
+  async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+    let n = buf.len().min(self.max_chunk).min(self.bytes.len());
+    buf.iter_mut().zip(self.bytes.drain(..n)).for_each(|(b, p)| *b = p);
+    Ok(n)
+  }
+}
+
+impl Write for Pipe {
+  async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+    let n = buf.len().min(self.max_chunk);
+    self.bytes.extend(&buf[..n]);
+    Ok(n)
+  }
+
+  async fn flush(&mut self) -> Result<(), Infallible> {
+    Ok(())
+  }
+}
+
+/// Nothing here ever actually waits, so just poll until done.
+fn block_on<F: Future>(f: F) -> F::Output {
+  let mut f = pin!(f);
+  let mut cx = Context::from_waker(Waker::noop());
+  loop {
+    if let Poll::Ready(r) = f.as_mut().poll(&mut cx) {
+      return r;
+    }
+  }
+}
+
+fn round_trip<const BUF: usize>(framing: Framing, fec: usize) {
+  let big: std::vec::Vec<u8> = (0..300).map(|i| i as u8).collect();
+  let msgs: [&[u8]; 4] = [b"hello", b"", &big, &[0, 0, 0xA9, 0]];
+
+  let mut encoder = AsyncEncoder::<2, 4, _, BUF>::new(Pipe { bytes: VecDeque::new(), max_chunk: 5 }).with_framing(framing).with_fec(fec);
+  for m in msgs {
+    block_on(encoder.write_frame(m)).unwrap();
+  }
+  let wire = std::mem::take(&mut encoder.writer_mut().bytes);
+
+  let mut decoder = AsyncDecoder::<2, 4, _, 512>::new(Pipe { bytes: wire, max_chunk: 3 }).with_framing(framing).with_fec(fec);
+  let mut out = Vec::<u8, 512>::new();
+  for m in msgs {
+    block_on(decoder.read_frame(&mut out)).unwrap();
+    assert_eq!(out, m);
+  }
+  assert_eq!(block_on(decoder.read_frame(&mut out)), Err(DencoderError::Eof));
+}
+
+#[test]
+fn magic_byte_round_trip() {
+  round_trip::<32>(Framing::MagicByte, 0);
+}
+
+#[test]
+fn cobs_fec_round_trip() {
+  round_trip::<32>(Framing::Cobs, 2);
+}
+
+#[test]
+fn one_byte_buffer_round_trip() {
+  for framing in [Framing::MagicByte, Framing::Cobs] {
+    for fec in [0, 2] {
+      round_trip::<1>(framing, fec);
+    }
+  }
+}
+
+#[test]
+fn bad_frames_are_reported_then_skipped() {
+  let mut encoder = AsyncEncoder::<2, 4, _, 32>::new(Pipe { bytes: VecDeque::new(), max_chunk: 64 });
+  block_on(encoder.write_frame(b"damaged")).unwrap();
+  block_on(encoder.write_frame(b"fine")).unwrap();
+  let mut wire = std::mem::take(&mut encoder.writer_mut().bytes);
+  wire[7] ^= 0x10;
+
+  let mut decoder = AsyncDecoder::<2, 4, _, 512>::new(Pipe { bytes: wire, max_chunk: 64 });
+  let mut out = Vec::<u8, 512>::new();
+  assert_eq!(block_on(decoder.read_frame(&mut out)), Err(DencoderError::MessageChecksum));
+  block_on(decoder.read_frame(&mut out)).unwrap();
+  assert_eq!(out, b"fine");
+}
@@ -0,0 +1,44 @@
+use erhannis_misc::dencoder::asynch::{AsyncDecoder, AsyncEncoder, TokioIo};
+use erhannis_misc::dencoder::{DencoderError, Framing};
+use heapless::Vec;
+
+async fn round_trip(framing: Framing) {
+  let big: std::vec::Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+  let msgs: std::vec::Vec<std::vec::Vec<u8>> = vec![b"hello".to_vec(), vec![], big, vec![0xA9; 40]];
+
+  // A tiny pipe, so the writer has to wait on the reader
+  let (a, b) = tokio::io::duplex(16);
+  let mut encoder = AsyncEncoder::<2, 4, _, 64>::new(TokioIo(a)).with_framing(framing);
+  let mut decoder = AsyncDecoder::<2, 4, _, 2048>::new(TokioIo(b)).with_framing(framing);
+
+  let sent = msgs.clone();
+  let writer = async move {
+    for m in &sent {
+      encoder.write_frame(m).await.unwrap();
+    }
+    // Dropping the encoder closes its end
+  };
+  let reader = async {
+    let mut out = Vec::<u8, 1024>::new();
+    let mut got = vec![];
+    loop {
+      match decoder.read_frame(&mut out).await {
+        Ok(()) => got.push(out.to_vec()),
+        Err(DencoderError::Eof) => return got,
+        Err(e) => panic!("{:?}", e),
+      }
+    }
+  };
+  let ((), got) = tokio::join!(writer, reader);
+  assert_eq!(got, msgs);
+}
+
+#[tokio::test]
+async fn duplex_round_trip() {
+  round_trip(Framing::MagicByte).await;
+}
+
+#[tokio::test]
+async fn duplex_cobs_round_trip() {
+  round_trip(Framing::Cobs).await;
+}
@@ -0,0 +1,114 @@
+use erhannis_misc::dencoder::auth::{AuthDecoder, AuthEncoder, HmacSha256, AUTH_OVERHEAD_BYTES};
+use erhannis_misc::dencoder::transport::{BufferSource, VecSink};
+use erhannis_misc::dencoder::{calcMsgSize, Decoder, DecoderT, DencoderError, Encoder, EncoderT};
+use heapless::Vec;
+
+const KEY: &[u8] = b"a very secret key shared by both";
+
+type Tx = AuthEncoder<Encoder<2, 4, VecSink<512>>>;
+type Rx = AuthDecoder<Decoder<2, 4, BufferSource<512>, 512>>;
+
+fn tx(key: &[u8]) -> Tx {
+  AuthEncoder::new(Encoder::new(VecSink::new()), key)
+}
+
+fn rx(key: &[u8]) -> Rx {
+  AuthDecoder::new(Decoder::new_plain(), key)
+}
+
+fn frame(tx: &mut Tx, msg: &[u8]) -> std::vec::Vec<u8> {
+  tx.write(msg).unwrap();
+  tx.encoder_mut().sink_mut().take().to_vec()
+}
+
+fn receive(rx: &mut Rx, frame: &[u8]) -> Result<std::vec::Vec<u8>, nb::Error<DencoderError<core::convert::Infallible>>> {
+  rx.decoder_mut().add(frame).unwrap();
+  let mut buf = Vec::<u8, 128>::new();
+  rx.read(&mut buf)?;
+  Ok(buf.to_vec())
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn hmac_rfc4231() {
+  // Test cases 2 and 6
+  assert_eq!(hex(&HmacSha256::new(b"Jefe").tag(&[b"what do ya want ", b"for nothing?"])), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+  assert_eq!(hex(&HmacSha256::new(&[0xAA; 131]).tag(&[b"Test Using Larger Than Block-Size Key - Hash Key First"])), "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+
+  let hmac = HmacSha256::new(b"Jefe");
+  let tag = hmac.tag(&[b"msg"]);
+  assert!(hmac.verify(&[b"m", b"sg"], &tag[..16]));
+  assert!(!hmac.verify(&[b"msh"], &tag[..16]));
+  assert!(!hmac.verify(&[b"msg"], &[]));
+}
+
+#[test]
+fn round_trip() {
+  let mut tx = tx(KEY);
+  let mut rx = rx(KEY);
+  for msg in [&b"hello"[..], b"", &[0xA9; 100]] {
+    let f = frame(&mut tx, msg);
+    assert_eq!(f.len(), calcMsgSize(2, 4, msg.len() + AUTH_OVERHEAD_BYTES));
+    assert_eq!(receive(&mut rx, &f), Ok(msg.to_vec()));
+  }
+  assert_eq!(tx.counter(), 3);
+  assert_eq!(rx.last_counter(), Some(2));
+
+  tx.write_vectored(&[b"two ", b"parts"]).unwrap();
+  let f = tx.encoder_mut().sink_mut().take();
+  assert_eq!(receive(&mut rx, &f), Ok(b"two parts".to_vec()));
+}
+
+#[test]
+fn forgeries_are_rejected() {
+  let mut rx = rx(KEY);
+  let f = frame(&mut tx(b"some other key"), b"open the door");
+  assert_eq!(receive(&mut rx, &f), Err(nb::Error::Other(DencoderError::Unauthenticated)));
+
+  // Too short to have a tag at all
+  let mut plain = Encoder::<2, 4, _>::new(VecSink::<64>::new());
+  plain.write(b"hi").unwrap();
+  assert_eq!(receive(&mut rx, &plain.sink_mut().take()), Err(nb::Error::Other(DencoderError::Unauthenticated)));
+  assert_eq!(rx.last_counter(), None);
+}
+
+#[test]
+fn replays_are_rejected() {
+  let mut tx = tx(KEY);
+  let mut rx = rx(KEY);
+  let first = frame(&mut tx, b"one");
+  let second = frame(&mut tx, b"two");
+  assert_eq!(receive(&mut rx, &second), Ok(b"two".to_vec()));
+  assert_eq!(receive(&mut rx, &second), Err(nb::Error::Other(DencoderError::Replayed)));
+  assert_eq!(receive(&mut rx, &first), Err(nb::Error::Other(DencoderError::Replayed)));
+  assert_eq!(receive(&mut rx, &frame(&mut tx, b"three")), Ok(b"three".to_vec()));
+}
+
+#[test]
+fn counters_carry_over_a_restart() {
+  let mut tx = tx(KEY).with_counter(1000);
+  let mut rx = rx(KEY).with_last_counter(1000);
+  let old = frame(&mut tx, b"old");
+  assert_eq!(receive(&mut rx, &old), Err(nb::Error::Other(DencoderError::Replayed)));
+  assert_eq!(receive(&mut rx, &frame(&mut tx, b"new")), Ok(b"new".to_vec()));
+  assert_eq!(rx.last_counter(), Some(1001));
+}
+
+#[test]
+fn exhausted_counter_sends_nothing() {
+  let mut spent = tx(KEY).with_counter(u64::MAX);
+  assert_eq!(spent.write(b"one too many"), Err(nb::Error::Other(DencoderError::CounterExhausted)));
+  assert_eq!(spent.write_vectored(&[b"or ", b"this"]), Err(nb::Error::Other(DencoderError::CounterExhausted)));
+  assert!(spent.encoder_mut().sink_mut().take().is_empty());
+  assert_eq!(spent.counter(), u64::MAX);
+
+  let mut tx = tx(KEY).with_counter(u64::MAX - 1);
+  let mut rx = rx(KEY).with_last_counter(u64::MAX - 2);
+  let f = frame(&mut tx, b"last");
+  assert_eq!(receive(&mut rx, &f), Ok(b"last".to_vec()));
+  assert_eq!(tx.write(b"after"), Err(nb::Error::Other(DencoderError::CounterExhausted)));
+  assert!(tx.encoder_mut().sink_mut().take().is_empty());
+}
@@ -0,0 +1,128 @@
+use erhannis_misc::dencoder::channel::{MuxError, MuxStats, Multiplexer};
+use erhannis_misc::dencoder::transport::{BufferSource, VecSink};
+use erhannis_misc::dencoder::{Decoder, DecoderT, Encoder, EncoderT};
+use heapless::Vec;
+
+const CONTROL: usize = 0;
+const TELEMETRY: usize = 1;
+const LOG: usize = 2;
+
+type Mux<const SINK: usize> = Multiplexer<Encoder<2, 4, VecSink<SINK>>, Decoder<2, 4, BufferSource<1024>, 1024>, 3, 64, 4>;
+
+fn mux<const SINK: usize>() -> Mux<SINK> {
+  Multiplexer::new(Encoder::new(VecSink::new()), Decoder::new_plain())
+}
+
+/// Moves whatever `a` has written over to `b`, and polls `b`.
+fn carry<const A: usize, const B: usize>(a: &mut Mux<A>, b: &mut Mux<B>) {
+  let bytes = a.encoder_mut().sink_mut().take();
+  b.decoder_mut().add(&bytes).unwrap();
+  b.poll().unwrap();
+}
+
+/// Channel numbers of the frames in `wire`, in order.
+fn channels_on(wire: &[u8]) -> std::vec::Vec<u8> {
+  let mut decoder = Decoder::<2, 4, _, 1024>::new_plain();
+  decoder.add(wire).unwrap();
+  let mut got = vec![];
+  let mut msg = Vec::<u8, 64>::new();
+  while decoder.read(&mut msg).is_ok() {
+    got.push(msg[0]);
+  }
+  got
+}
+
+#[test]
+fn messages_come_out_on_their_channels() {
+  let mut a = mux::<1024>();
+  let mut b = mux::<1024>();
+  a.send(TELEMETRY, b"t1").unwrap();
+  a.send(LOG, b"l1").unwrap();
+  a.send(TELEMETRY, b"t2").unwrap();
+  a.send(CONTROL, b"").unwrap();
+  a.poll().unwrap();
+  carry(&mut a, &mut b);
+  assert_eq!((b.pending(CONTROL), b.pending(TELEMETRY), b.pending(LOG)), (1, 2, 1));
+
+  let mut buf = Vec::<u8, 64>::new();
+  b.receive(TELEMETRY, &mut buf).unwrap();
+  assert_eq!(&buf[..], b"t1");
+  b.receive(TELEMETRY, &mut buf).unwrap();
+  assert_eq!(&buf[..], b"t2");
+  assert_eq!(b.receive(TELEMETRY, &mut buf), Err(nb::Error::WouldBlock));
+  b.receive(LOG, &mut buf).unwrap();
+  assert_eq!(&buf[..], b"l1");
+  b.receive(CONTROL, &mut buf).unwrap();
+  assert_eq!(&buf[..], b"");
+  assert_eq!(b.stats(), MuxStats::default());
+}
+
+#[test]
+fn higher_priority_goes_first() {
+  let mut a = mux::<1024>().with_priority(CONTROL, 1);
+  for _ in 0..3 {
+    a.send(TELEMETRY, &[0xAA; 40]).unwrap();
+    a.send(LOG, &[0xBB; 40]).unwrap();
+  }
+  a.send(CONTROL, b"stop").unwrap();
+  a.poll().unwrap();
+  let wire = a.encoder_mut().sink_mut().take();
+  // Control, then the rest taking turns
+  assert_eq!(channels_on(&wire), [0, 1, 2, 1, 2, 1, 2]);
+}
+
+#[test]
+fn frame_partway_out_finishes_first() {
+  // Room for a frame and a half
+  let mut a = mux::<80>().with_priority(CONTROL, 1);
+  a.send(TELEMETRY, &[0xAA; 40]).unwrap();
+  a.send(TELEMETRY, &[0xAA; 40]).unwrap();
+  a.poll().unwrap();
+  let mut wire = a.encoder_mut().sink_mut().take().to_vec();
+  assert_eq!(a.queued(TELEMETRY), 1);
+  a.send(CONTROL, b"stop").unwrap();
+  a.poll().unwrap();
+  wire.extend_from_slice(&a.encoder_mut().sink_mut().take());
+  assert_eq!(channels_on(&wire), [1, 1, 0]);
+  assert_eq!(a.queued(TELEMETRY), 0);
+  assert_eq!(a.queued(CONTROL), 0);
+}
+
+#[test]
+fn full_queues() {
+  let mut a = mux::<1024>();
+  let mut b = mux::<1024>();
+  for i in 0..4 {
+    a.send(LOG, &[i]).unwrap();
+  }
+  assert_eq!(a.send(LOG, b"more"), Err(nb::Error::WouldBlock));
+  a.poll().unwrap();
+  a.send(LOG, b"more").unwrap();
+  a.poll().unwrap();
+  carry(&mut a, &mut b);
+  assert_eq!(b.pending(LOG), 4);
+  assert_eq!(b.stats().overflows, 1);
+}
+
+#[test]
+fn bad_input() {
+  let mut a = mux::<1024>();
+  let mut b = mux::<1024>();
+  assert_eq!(a.send(3, b"x"), Err(nb::Error::Other(MuxError::NoSuchChannel)));
+  assert_eq!(a.send(LOG, &[0; 64]), Err(nb::Error::Other(MuxError::MessageTooLong)));
+  a.send(LOG, &[0; 63]).unwrap();
+  a.poll().unwrap();
+  carry(&mut a, &mut b);
+  let mut small = Vec::<u8, 16>::new();
+  assert_eq!(b.receive(LOG, &mut small), Err(nb::Error::Other(MuxError::MessageTooBig)));
+  assert_eq!(b.pending(LOG), 1);
+  assert_eq!(b.receive(7, &mut small), Err(nb::Error::Other(MuxError::NoSuchChannel)));
+
+  // Frames from something that isn't a Multiplexer with the same channels
+  let mut wire = Encoder::<2, 4, _>::new(VecSink::<64>::new());
+  wire.write(&[5, 1, 2]).unwrap();
+  wire.write(&[]).unwrap();
+  b.decoder_mut().add(&wire.sink_mut().take()).unwrap();
+  b.poll().unwrap();
+  assert_eq!(b.stats().bad_frames, 2);
+}
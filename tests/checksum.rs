@@ -0,0 +1,35 @@
+use erhannis_misc::dencoder::checksum::{Checksum, Crc16, Crc32, Crc8, Fletcher16, NoChecksum, Sha256Checksum, XxHash32};
+
+fn check<CK: Checksum>(data: &[u8]) -> std::vec::Vec<u8> {
+  CK::digest(data)[..CK::OUTPUT_BYTES].to_vec()
+}
+
+#[test]
+fn known_answers() {
+  // The usual "123456789" check values
+  assert_eq!(check::<Crc8>(b"123456789"), [0xF4]);
+  assert_eq!(check::<Crc16>(b"123456789"), [0x29, 0xB1]);
+  assert_eq!(check::<Crc32>(b"123456789"), [0xCB, 0xF4, 0x39, 0x26]);
+  assert_eq!(check::<Fletcher16>(b"abcde"), [0xC8, 0xF0]);
+  assert_eq!(check::<XxHash32>(b""), [0x02, 0xCC, 0x5D, 0x05]);
+  assert_eq!(check::<Sha256Checksum>(b"")[..4], [0xE3, 0xB0, 0xC4, 0x42]);
+  assert_eq!(check::<NoChecksum>(b"123456789"), []);
+}
+
+#[test]
+fn incremental_matches_one_shot() {
+  fn split<CK: Checksum>(data: &[u8]) {
+    let mut c = CK::default();
+    for chunk in data.chunks(7) {
+      c.update(chunk);
+    }
+    assert_eq!(c.finalize(), CK::digest(data));
+  }
+  let data: std::vec::Vec<u8> = (0..100).collect();
+  split::<Sha256Checksum>(&data);
+  split::<Crc8>(&data);
+  split::<Crc16>(&data);
+  split::<Crc32>(&data);
+  split::<Fletcher16>(&data);
+  split::<XxHash32>(&data);
+}
@@ -0,0 +1,79 @@
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use tokio_util::codec::{Decoder as _, Encoder as _, Framed, FramedRead};
+
+use erhannis_misc::dencoder::codec::DencoderCodec;
+use erhannis_misc::dencoder::{calcMsgSize, Encoder, Framing};
+
+fn frame(msg: &[u8]) -> std::vec::Vec<u8> {
+  let mut out = vec![0u8; calcMsgSize(2, 4, msg.len())];
+  Encoder::<2, 4, ()>::write_plain(msg, &mut out).unwrap();
+  out
+}
+
+#[test]
+fn encodes_like_write_plain() {
+  let mut codec = DencoderCodec::<2, 4, 256>::new();
+  let mut dst = BytesMut::new();
+  codec.encode(Bytes::from_static(b"hello"), &mut dst).unwrap();
+  codec.encode(Bytes::new(), &mut dst).unwrap();
+  assert_eq!(&dst[..], &[frame(b"hello"), frame(b"")].concat()[..]);
+
+  let mut codec = DencoderCodec::<1, 4, 256>::new();
+  let e = codec.encode(Bytes::from(vec![0; 256]), &mut dst).unwrap_err();
+  assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn resyncs_past_garbage_and_damage() {
+  let mut damaged = frame(b"damaged");
+  damaged[9] ^= 0x40;
+  let wire = [&[0xA9, 0x00, 0x13][..], &frame(b"first"), &damaged, &[0xA9], &frame(b"second")].concat();
+
+  // A byte at a time, the way bytes might actually turn up
+  let mut codec = DencoderCodec::<2, 4, 256>::new();
+  let mut src = BytesMut::new();
+  let mut msgs = vec![];
+  for &b in &wire {
+    src.extend_from_slice(&[b]);
+    while let Some(m) = codec.decode(&mut src).unwrap() {
+      msgs.push(m.to_vec());
+    }
+  }
+  assert_eq!(msgs, [b"first".to_vec(), b"second".to_vec()]);
+  assert!(codec.dropped_frames() >= 1);
+}
+
+#[tokio::test]
+async fn framed_read_all_at_once() {
+  let wire = [frame(b"one"), frame(b"two"), frame(b"three")].concat();
+  let msgs: std::vec::Vec<_> = FramedRead::new(&wire[..], DencoderCodec::<2, 4, 256>::new())
+    .map(|m| m.unwrap().to_vec())
+    .collect()
+    .await;
+  assert_eq!(msgs, [b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]);
+}
+
+#[tokio::test]
+async fn framed_duplex_round_trip() {
+  let (a, b) = tokio::io::duplex(32);
+  let mut tx = Framed::new(a, DencoderCodec::<2, 4, 2048>::new().with_framing(Framing::Cobs).with_fec(2));
+  let mut rx = Framed::new(b, DencoderCodec::<2, 4, 2048>::new().with_framing(Framing::Cobs).with_fec(2));
+  let msgs: std::vec::Vec<Bytes> = (0..20).map(|i| Bytes::from(vec![i as u8; i * 50])).collect();
+
+  let sent = msgs.clone();
+  let writer = async move {
+    for m in sent {
+      tx.send(m).await.unwrap();
+    }
+  };
+  let reader = async {
+    let mut got = vec![];
+    while let Some(m) = rx.next().await {
+      got.push(m.unwrap().freeze());
+    }
+    got
+  };
+  let ((), got) = tokio::join!(writer, reader);
+  assert_eq!(got, msgs);
+}
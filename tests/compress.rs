@@ -0,0 +1,102 @@
+use erhannis_misc::dencoder::compress::{compress_buffer_size, CompressDecoder, CompressEncoder, CompressionStats, COMPRESS_HEADER_BYTES, FLAG_LZ4, FLAG_RAW};
+use erhannis_misc::dencoder::transport::{BufferSource, VecSink};
+use erhannis_misc::dencoder::{calcMsgSize, Decoder, DecoderT, DencoderError, Encoder, EncoderT};
+use heapless::Vec;
+
+type Tx = CompressEncoder<Encoder<2, 4, VecSink<1024>>, { compress_buffer_size(200) }>;
+type Rx = CompressDecoder<Decoder<2, 4, BufferSource<1024>, 1024>, 256>;
+
+fn tx() -> Tx {
+  CompressEncoder::new(Encoder::new(VecSink::new()))
+}
+
+fn rx() -> Rx {
+  CompressDecoder::new(Decoder::new_plain())
+}
+
+fn receive(rx: &mut Rx, frame: &[u8]) -> Result<std::vec::Vec<u8>, nb::Error<DencoderError<core::convert::Infallible>>> {
+  rx.decoder_mut().add(frame).unwrap();
+  let mut buf = Vec::<u8, 256>::new();
+  rx.read(&mut buf)?;
+  Ok(buf.to_vec())
+}
+
+/// The message `wire` carries, undecompressed.
+fn payload(wire: &[u8]) -> std::vec::Vec<u8> {
+  let mut decoder = Decoder::<2, 4, _, 1024>::new_plain();
+  decoder.add(wire).unwrap();
+  let mut msg = Vec::<u8, 256>::new();
+  decoder.read(&mut msg).unwrap();
+  msg.to_vec()
+}
+
+#[test]
+fn round_trip() {
+  let mut tx = tx();
+  let mut rx = rx();
+  let text = b"the quick brown fox, the quick brown fox, the quick brown fox, the quick brown fox";
+  let noise: std::vec::Vec<u8> = (0..100u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+  for msg in [&text[..], b"", b"hi", &[0; 200], &noise] {
+    tx.write(msg).unwrap();
+    let f = tx.encoder_mut().sink_mut().take();
+    assert!(f.len() <= calcMsgSize(2, 4, msg.len() + COMPRESS_HEADER_BYTES));
+    assert_eq!(receive(&mut rx, &f), Ok(msg.to_vec()));
+  }
+  let stats = tx.stats();
+  assert_eq!((stats.messages, stats.compressed), (5, 2));
+  assert_eq!(rx.stats(), stats);
+  assert!(stats.ratio() < 0.5);
+  assert_eq!(CompressionStats::default().ratio(), 1.0);
+}
+
+#[test]
+fn only_goes_compressed_when_shorter() {
+  let mut tx = tx();
+  tx.write(&[7; 150]).unwrap();
+  let p = payload(&tx.encoder_mut().sink_mut().take());
+  assert_eq!(p[0], FLAG_LZ4);
+  assert!(p.len() < 20);
+
+  tx.write(b"abc").unwrap();
+  assert_eq!(payload(&tx.encoder_mut().sink_mut().take()), [FLAG_RAW, b'a', b'b', b'c']);
+
+  // Too long for the buffer
+  tx.write(&[7; 250]).unwrap();
+  assert_eq!(payload(&tx.encoder_mut().sink_mut().take())[0], FLAG_RAW);
+}
+
+#[test]
+fn vectored() {
+  let mut tx = tx();
+  let mut rx = rx();
+  tx.write_vectored(&[&[1; 40], &[2; 40]]).unwrap();
+  let f = tx.encoder_mut().sink_mut().take();
+  assert_eq!(payload(&f)[0], FLAG_LZ4);
+  let mut expected = vec![1; 40];
+  expected.extend_from_slice(&[2; 40]);
+  assert_eq!(receive(&mut rx, &f), Ok(expected));
+
+  // No room to gather, so raw
+  tx.write_vectored(&[&[1; 100], &[2; 100]]).unwrap();
+  let f = tx.encoder_mut().sink_mut().take();
+  assert_eq!(payload(&f)[0], FLAG_RAW);
+  assert_eq!(receive(&mut rx, &f).unwrap().len(), 200);
+}
+
+#[test]
+fn bad_payloads() {
+  let mut rx = rx();
+  let mut plain = Encoder::<2, 4, _>::new(VecSink::<64>::new());
+  for msg in [&[][..], &[9, 1, 2], &[FLAG_LZ4, 0xF0, 1]] {
+    plain.write(msg).unwrap();
+    assert_eq!(receive(&mut rx, &plain.sink_mut().take()), Err(nb::Error::Other(DencoderError::Decompression)));
+  }
+  assert_eq!(rx.stats(), CompressionStats::default());
+
+  // Decompresses to more than the caller's buffer holds
+  let mut tx = tx();
+  tx.write(&[0; 200]).unwrap();
+  rx.decoder_mut().add(&tx.encoder_mut().sink_mut().take()).unwrap();
+  let mut small = Vec::<u8, 100>::new();
+  assert_eq!(rx.read(&mut small), Err(nb::Error::Other(DencoderError::MessageTooBig)));
+}
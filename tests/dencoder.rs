@@ -0,0 +1,769 @@
+use std::convert::Infallible;
+use std::collections::VecDeque;
+
+use erhannis_misc::dencoder::checksum::{Checksum, Crc16, Crc32, Crc8, Fletcher16, NoChecksum, Sha256Checksum, XxHash32};
+use erhannis_misc::dencoder::transport::{BufferSource, VecSink};
+use erhannis_misc::dencoder::{calc_cobs_msg_size, calcMsgSize, Decoder, DecoderT, DencoderError, Encoder, EncoderStats, EncoderT, Endianness, FrameFormat, Framing, InPlaceError, TransmissionStatus, FORMAT_VERSION, FRAME_HEADER_BYTES};
+use erhannis_misc::rate_meter::RateMeter;
+use heapless::Vec;
+
+const MAGIC_BYTE: u8 = 0xA9;
+
+fn frame<const L: usize, const C: usize>(msg: &[u8]) -> std::vec::Vec<u8> {
+  let mut out = vec![0u8; calcMsgSize(L, C, msg.len())];
+  Encoder::<L, C, ()>::write_plain(msg, &mut out).unwrap();
+  out
+}
+
+fn cobs_frame<const L: usize, const C: usize>(msg: &[u8]) -> std::vec::Vec<u8> {
+  let mut out = vec![0u8; calc_cobs_msg_size(L, C, msg.len())];
+  let n = Encoder::<L, C, ()>::write_plain_cobs(msg, &mut out).unwrap();
+  out.truncate(n);
+  out
+}
+
+/// A sink that appends to `out`, taking at most `step` bytes a call.
+fn trickle_sink(out: &mut std::vec::Vec<u8>, step: usize) -> impl FnMut(&[&[u8]]) -> Result<TransmissionStatus, nb::Error<Infallible>> + '_ {
+  move |buffers: &[&[u8]]| {
+    let mut taken = 0;
+    for b in buffers {
+      let n = b.len().min(step - taken);
+      out.extend_from_slice(&b[..n]);
+      taken += n;
+      if n < b.len() {
+        return Ok(TransmissionStatus::Partial(taken));
+      }
+    }
+    Ok(TransmissionStatus::Complete)
+  }
+}
+
+/// Feeds `input` to a fresh decoder `chunk` bytes at a time, and returns every message it produced.
+/// Validation errors are expected along the way and just skipped over.
+fn decode_all<const L: usize, const C: usize>(input: &[u8], chunk: usize) -> std::vec::Vec<std::vec::Vec<u8>> {
+  decode_all_framed::<L, C>(Framing::MagicByte, input, chunk)
+}
+
+fn decode_all_framed<const L: usize, const C: usize>(framing: Framing, input: &[u8], chunk: usize) -> std::vec::Vec<std::vec::Vec<u8>> {
+  let mut decoder = Decoder::<L, C, _, 512>::new_plain().with_framing(framing);
+  let mut out = Vec::<u8, 256>::new();
+  let mut msgs = vec![];
+  for c in input.chunks(chunk) {
+    decoder.add(c).unwrap();
+    loop {
+      match decoder.read(&mut out) {
+        Ok(()) => msgs.push(out.to_vec()),
+        Err(nb::Error::WouldBlock) => break,
+        Err(nb::Error::Other(_)) => (),
+      }
+    }
+  }
+  msgs
+}
+
+#[test]
+fn payloads_full_of_magic_bytes() {
+  let msgs: [&[u8]; 4] = [&[MAGIC_BYTE; 10], &[MAGIC_BYTE, 0, MAGIC_BYTE], &[], &[1, MAGIC_BYTE, MAGIC_BYTE, 2]];
+  let input: std::vec::Vec<u8> = msgs.iter().flat_map(|m| frame::<2, 4>(m)).collect();
+  for chunk in [1, 3, input.len()] {
+    assert_eq!(decode_all::<2, 4>(&input, chunk), msgs);
+  }
+}
+
+#[test]
+fn garbage_prefix_with_false_magic() {
+  let msg = b"hello";
+  let prefixes: [&[u8]; 5] = [
+    &[0x00, 0x01, 0x02],
+    &[MAGIC_BYTE],
+    &[MAGIC_BYTE, 0x00],
+    &[MAGIC_BYTE, 0x00, 0x05, MAGIC_BYTE],
+    &[0x13, MAGIC_BYTE, 0xFF, 0xFF, MAGIC_BYTE, MAGIC_BYTE],
+  ];
+  for prefix in prefixes {
+    let mut input = prefix.to_vec();
+    input.extend(frame::<2, 4>(msg));
+    for chunk in [1, input.len()] {
+      assert_eq!(decode_all::<2, 4>(&input, chunk), [msg], "prefix {:02x?}", prefix);
+    }
+  }
+}
+
+#[test]
+fn frames_inside_truncated_frame_are_recovered() {
+  // The first frame is cut off mid-body, so the decoder reads the next frames as its body.
+  // Once its checksum fails, those bytes should be rescanned rather than thrown away.
+  let long = [0x55u8; 60];
+  let mut input = frame::<2, 4>(&long);
+  input.truncate(20);
+  let msgs: [&[u8]; 3] = [b"second", &[MAGIC_BYTE; 5], b"fourth"];
+  for m in msgs {
+    input.extend(frame::<2, 4>(m));
+  }
+  input.extend(frame::<2, 4>(&long)); // Enough trailing bytes to finish off the bogus first frame
+  for chunk in [1, 7, input.len()] {
+    assert_eq!(decode_all::<2, 4>(&input, chunk), [&msgs[..], &[&long[..]]].concat());
+  }
+}
+
+#[test]
+fn cobs_round_trip() {
+  let runs: std::vec::Vec<std::vec::Vec<u8>> = vec![
+    vec![],
+    vec![0],
+    vec![0, 0, 0],
+    vec![MAGIC_BYTE, 0, MAGIC_BYTE],
+    vec![1; 253],
+    vec![1; 254],
+    vec![1; 255],
+    (0..=255).map(|i| i as u8).collect(),
+  ];
+  let mut input = vec![];
+  for m in &runs {
+    let f = cobs_frame::<2, 4>(m);
+    assert!(f.len() <= calc_cobs_msg_size(2, 4, m.len()));
+    assert_eq!(f.iter().position(|&b| b == 0), Some(f.len() - 1));
+    input.extend(f);
+  }
+  for chunk in [1, 5, 300] {
+    assert_eq!(decode_all_framed::<2, 4>(Framing::Cobs, &input, chunk), runs);
+  }
+}
+
+#[test]
+fn cobs_drops_only_the_bad_frame() {
+  let msgs: [&[u8]; 3] = [b"first", b"second", b"third"];
+  let mut input = vec![0x12, MAGIC_BYTE, 0x34, 0x00]; // Garbage, then a delimiter
+  input.extend(cobs_frame::<2, 4>(msgs[0]));
+  let mut bad = cobs_frame::<2, 4>(b"corrupted");
+  bad[6] ^= 0x40;
+  input.extend(bad);
+  input.extend(&cobs_frame::<2, 4>(b"truncated")[..5]);
+  input.push(0x00);
+  input.extend(cobs_frame::<2, 4>(msgs[1]));
+  input.extend([0x00, 0x00]); // Empty frames are ignored
+  input.extend(cobs_frame::<2, 4>(msgs[2]));
+  for chunk in [1, input.len()] {
+    assert_eq!(decode_all_framed::<2, 4>(Framing::Cobs, &input, chunk), msgs);
+  }
+}
+
+fn hex(s: &str) -> std::vec::Vec<u8> {
+  (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+}
+
+#[test]
+fn sha256_frames_match_original_format() {
+  // Frames produced by the encoder before checksums were pluggable
+  let fox = b"The quick brown fox jumps over the lazy dog";
+  let cases: [(&[u8], &str, &str); 4] = [
+    (b"", "a9000096a2e3b0c442", "a9006ee3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+    (b"hello", "a900059f1a68656c6c6f2cf24dba", "a905e768656c6c6f2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"),
+    (&[MAGIC_BYTE, 0x00, MAGIC_BYTE, 0xFF], "a900044f35a900a9ffd836ddec", "a904e5a900a9ffd836ddec763b2df875b744995f56e27b03c22fb69570b1b2feb48c5405b322b0"),
+    (fox, "a9002b541254686520717569636b2062726f776e20666f78206a756d7073206f76657220746865206c617a7920646f67d7a8fbb3",
+      "a92ba354686520717569636b2062726f776e20666f78206a756d7073206f76657220746865206c617a7920646f67d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592"),
+  ];
+  for (msg, l2c4, l1c32) in cases {
+    assert_eq!(frame::<2, 4>(msg), hex(l2c4));
+    let mut out = vec![0u8; calcMsgSize(2, 4, msg.len())];
+    Encoder::<2, 4, (), Sha256Checksum>::write_plain(msg, &mut out).unwrap();
+    assert_eq!(out, hex(l2c4));
+    assert_eq!(frame::<1, 32>(msg), hex(l1c32));
+    assert_eq!(decode_all::<1, 32>(&hex(l1c32), 1), [msg]);
+  }
+}
+
+fn checksum_round_trip<CK: Checksum, const C: usize>() {
+  let msgs: [&[u8]; 3] = [b"", b"hello", &[MAGIC_BYTE; 40]];
+  let mut input = vec![0x00, MAGIC_BYTE];
+  for m in msgs {
+    let mut out = vec![0u8; calcMsgSize(2, C, m.len())];
+    Encoder::<2, C, (), CK>::write_plain(m, &mut out).unwrap();
+    input.extend(out);
+  }
+  let mut decoder = Decoder::<2, C, _, 512, CK>::new_plain();
+  decoder.add(&input).unwrap();
+  let mut out = Vec::<u8, 256>::new();
+  let mut got = vec![];
+  loop {
+    match decoder.read(&mut out) {
+      Ok(()) => got.push(out.to_vec()),
+      Err(nb::Error::WouldBlock) => break,
+      Err(nb::Error::Other(_)) => (),
+    }
+  }
+  assert_eq!(got, msgs);
+}
+
+#[test]
+fn every_checksum_round_trips() {
+  checksum_round_trip::<Sha256Checksum, 4>();
+  checksum_round_trip::<Crc8, 1>();
+  checksum_round_trip::<Crc16, 2>();
+  checksum_round_trip::<Crc32, 4>();
+  checksum_round_trip::<Fletcher16, 2>();
+  checksum_round_trip::<XxHash32, 4>();
+  checksum_round_trip::<NoChecksum, 0>();
+}
+
+fn headered_frame<const L: usize, const C: usize, CK: Checksum>(msg: &[u8]) -> std::vec::Vec<u8> {
+  let mut out = vec![0u8; calcMsgSize(L, C, msg.len()) + FRAME_HEADER_BYTES];
+  let mut encoder = Encoder::<L, C, _, CK>::new_plain(&mut out).with_header(true);
+  encoder.write(msg).unwrap();
+  assert_eq!(encoder.written(), calcMsgSize(L, C, msg.len()) + FRAME_HEADER_BYTES);
+  out
+}
+
+#[test]
+fn headered_and_plain_frames_mix() {
+  let f = headered_frame::<2, 4, Sha256Checksum>(b"hello");
+  assert_eq!(f[..3], [0xA5, 0x49, 0x04]); // v1, SHA-256, L=2, C=4
+  let msgs: [&[u8]; 4] = [b"hello", b"plain", &[0xA5; 6], b""];
+  let mut input = vec![0xA5, 0x00];
+  input.extend(f);
+  input.extend(frame::<2, 4>(msgs[1]));
+  input.extend(headered_frame::<2, 4, Sha256Checksum>(msgs[2]));
+  input.extend(headered_frame::<2, 4, Sha256Checksum>(msgs[3]));
+  for chunk in [1, 4, input.len()] {
+    assert_eq!(decode_all::<2, 4>(&input, chunk), msgs);
+  }
+}
+
+#[test]
+fn mismatched_sender_is_reported() {
+  let mut input = headered_frame::<2, 4, Crc32>(b"wrong checksum");
+  input.extend(headered_frame::<1, 4, Sha256Checksum>(b"wrong length"));
+  input.extend(headered_frame::<2, 8, Sha256Checksum>(b"wrong checksum length"));
+  let mut little = [0u8; 32];
+  let mut encoder = Encoder::<2, 4, _>::new_plain(&mut little).with_header(true).with_endianness(Endianness::Little);
+  encoder.write(b"wrong endianness").unwrap();
+  let n = encoder.written();
+  input.extend(&little[..n]);
+  input.extend(headered_frame::<2, 4, Sha256Checksum>(b"right"));
+  let mut decoder = Decoder::<2, 4, _, 512>::new_plain();
+  decoder.add(&input).unwrap();
+  let mut out = Vec::<u8, 256>::new();
+  let mismatch = |checksum_id, len_prefix_bytes, checksum_bytes| {
+    Err(nb::Error::Other(DencoderError::FormatMismatch(FrameFormat { version: FORMAT_VERSION, checksum_id, len_prefix_bytes, checksum_bytes, endianness: Endianness::Big, fec: false })))
+  };
+  assert_eq!(decoder.read(&mut out), mismatch(4, 2, 4));
+  assert_eq!(decoder.read(&mut out), mismatch(1, 1, 4));
+  assert_eq!(decoder.read(&mut out), mismatch(1, 2, 8));
+  let little_mismatch = FrameFormat { endianness: Endianness::Little, ..FrameFormat::new(2, 4, 1, Endianness::Big, false) };
+  assert_eq!(decoder.read(&mut out), Err(nb::Error::Other(DencoderError::FormatMismatch(little_mismatch))));
+  assert_eq!(decoder.read(&mut out), Ok(()));
+  assert_eq!(out, b"right");
+  assert_eq!(decoder.read(&mut out), Err(nb::Error::WouldBlock));
+}
+
+#[test]
+fn noise_is_not_a_mismatched_sender() {
+  // Plenty of 0xA5s, each followed by what looks like some other sender's header
+  let mut rng = 12345u32;
+  let noise: std::vec::Vec<u8> = (0..20000).map(|_| {
+    rng = rng.wrapping_mul(1664525).wrapping_add(1013904223);
+    if (rng >> 24) < 32 { 0xA5 } else { (rng >> 16) as u8 }
+  }).collect();
+  let mut input = noise.clone();
+  input.extend(headered_frame::<2, 4, Sha256Checksum>(b"real"));
+
+  let mut decoder = Decoder::<2, 4, _, 64>::new_plain();
+  let mut out = Vec::<u8, 16>::new();
+  let mut got = vec![];
+  for c in input.chunks(7) {
+    decoder.add(c).unwrap();
+    loop {
+      match decoder.read(&mut out) {
+        Ok(()) => got.push(out.to_vec()),
+        Err(nb::Error::WouldBlock) => break,
+        Err(nb::Error::Other(DencoderError::FormatMismatch(ff))) => panic!("noise reported as {:?}", ff),
+        Err(nb::Error::Other(_)) => (),
+      }
+    }
+  }
+  assert_eq!(got.last().unwrap(), b"real");
+  assert_eq!(decoder.stats().format_mismatches, 0);
+
+  let mut in_place = Decoder::<2, 4, _, 0>::new_plain();
+  let mut rest = &mut input[..];
+  while !rest.is_empty() {
+    match in_place.decode_in_place(rest) {
+      Ok((_, consumed)) => rest = &mut rest[consumed..],
+      Err(e) => {
+        assert!(!matches!(e.error, nb::Error::Other(DencoderError::FormatMismatch(_))), "noise reported as {:?}", e.error);
+        if e.error == nb::Error::WouldBlock {
+          break;
+        }
+        rest = &mut rest[e.consumed..];
+      },
+    }
+  }
+  assert_eq!(in_place.stats().format_mismatches, 0);
+}
+
+#[test]
+fn headered_cobs_round_trip() {
+  let msgs: [&[u8]; 2] = [b"", &[0, 0xA5, 0]];
+  let mut out = [0u8; 64];
+  let mut encoder = Encoder::<2, 4, _>::new_plain(&mut out).with_header(true).with_framing(Framing::Cobs);
+  for m in msgs {
+    encoder.write(m).unwrap();
+  }
+  let n = encoder.written();
+  assert_eq!(decode_all_framed::<2, 4>(Framing::Cobs, &out[..n], 1), msgs);
+}
+
+fn endianness_round_trip<const L: usize>() {
+  // 300 bytes needs two length bytes, so the byte order shows
+  let msgs: [&[u8]; 3] = [b"", &[MAGIC_BYTE; 3], &[0x5A; 300]];
+  for endianness in [Endianness::Big, Endianness::Little] {
+    let mut input = vec![];
+    let mut sent = vec![];
+    for m in msgs {
+      let mut out = vec![0u8; calcMsgSize(L, 4, m.len())];
+      let mut encoder = Encoder::<L, 4, _>::new_plain(&mut out).with_endianness(endianness);
+      if L == 1 && m.len() > 255 {
+        assert_eq!(encoder.write(m), Err(nb::Error::Other(DencoderError::MessageTooLong)));
+        continue;
+      }
+      encoder.write(m).unwrap();
+      let mut expected_len = (m.len() as u64).to_le_bytes()[..L].to_vec();
+      if endianness == Endianness::Big {
+        expected_len.reverse();
+      }
+      assert_eq!(out[1..1 + L], expected_len, "L={} {:?}", L, endianness);
+      input.extend(out);
+      sent.push(m);
+    }
+    let mut decoder = Decoder::<L, 4, _, 1024>::new_plain().with_endianness(endianness);
+    decoder.add(&input).unwrap();
+    let mut out = Vec::<u8, 512>::new();
+    for m in &sent {
+      assert_eq!(decoder.read(&mut out), Ok(()), "L={} {:?}", L, endianness);
+      assert_eq!(&out, m);
+    }
+    assert_eq!(decoder.read(&mut out), Err(nb::Error::WouldBlock));
+  }
+}
+
+#[test]
+fn length_prefix_endianness() {
+  endianness_round_trip::<1>();
+  endianness_round_trip::<2>();
+  endianness_round_trip::<3>();
+  endianness_round_trip::<4>();
+  endianness_round_trip::<5>();
+  endianness_round_trip::<6>();
+  endianness_round_trip::<7>();
+  endianness_round_trip::<8>();
+}
+
+#[test]
+fn fec_repairs_damaged_frames() {
+  let long: std::vec::Vec<u8> = (0..600u32).map(|i| (i * 7) as u8).collect();
+  let msgs: [&[u8]; 4] = [b"", b"short", &long, b"last"];
+  let mut frames = vec![];
+  for m in msgs {
+    let mut out = vec![0u8; 1024];
+    let mut encoder = Encoder::<2, 4, _>::new_plain(&mut out).with_fec(3).with_header(true);
+    let size = encoder.frame_size(m.len());
+    encoder.write(m).unwrap();
+    assert_eq!(encoder.written(), size);
+    out.truncate(size);
+    frames.push(out);
+  }
+  assert_eq!(frames[1].len(), calcMsgSize(2, 4, 5) + FRAME_HEADER_BYTES + 6);
+  assert_eq!(frames[2].len(), calcMsgSize(2, 4, 600) + FRAME_HEADER_BYTES + 3 * 6);
+
+  let body = 1 + FRAME_HEADER_BYTES + 4;
+  frames[1][body] ^= 0xFF; // 1 bad byte
+  for k in 0..3 {
+    frames[2][body + k * 11] ^= 0x10; // 3 in the first block, which is as many as it can take
+    frames[2][body + 300 + k] ^= 0x20; // And 3 in the second
+  }
+  for k in 0..4 {
+    frames[3][body + k] ^= 0x01; // Too many
+  }
+  let input = frames.concat();
+
+  let mut decoder = Decoder::<2, 4, _, 1024>::new_plain().with_fec(3);
+  decoder.add(&input).unwrap();
+  let mut out = Vec::<u8, 1024>::new();
+  for m in &msgs[..3] {
+    assert_eq!(decoder.read(&mut out), Ok(()));
+    assert_eq!(&out, m);
+  }
+  assert!(matches!(decoder.read(&mut out), Err(nb::Error::Other(DencoderError::Uncorrectable | DencoderError::MessageChecksum))));
+  let stats = decoder.fec_stats();
+  assert_eq!((stats.corrected_frames, stats.corrected_bytes, stats.uncorrectable_frames), (2, 7, 1));
+}
+
+#[test]
+fn fec_with_cobs() {
+  // FEC sits inside COBS, so it can fix damage that leaves the COBS codes intact
+  let msg = [0x55u8; 400];
+  let mut out = [0u8; 1024];
+  let mut encoder = Encoder::<2, 4, _>::new_plain(&mut out).with_fec(2).with_framing(Framing::Cobs);
+  encoder.write(&msg).unwrap();
+  let n = encoder.written();
+  let mut damaged = out[..n].to_vec();
+  let i = damaged.iter().rposition(|&b| b == 0x55).unwrap();
+  damaged[i] = 0x56;
+  let mut decoder = Decoder::<2, 4, _, 1024>::new_plain().with_fec(2).with_framing(Framing::Cobs);
+  decoder.add(&damaged).unwrap();
+  let mut got = Vec::<u8, 512>::new();
+  assert_eq!(decoder.read(&mut got), Ok(()));
+  assert_eq!(got, msg);
+  assert_eq!(decoder.fec_stats().corrected_bytes, 1);
+}
+
+#[test]
+fn closures_as_transport() {
+  let mut wire = std::vec::Vec::new();
+  {
+    let mut encoder = Encoder::<2, 4, _>::new(|buffers: &[&[u8]]| -> Result<TransmissionStatus, nb::Error<Infallible>> {
+      buffers.iter().for_each(|b| wire.extend_from_slice(b));
+      Ok(TransmissionStatus::Complete)
+    });
+    encoder.write(b"first").unwrap();
+    encoder.write(b"second").unwrap();
+  }
+  assert_eq!(wire, [frame::<2, 4>(b"first"), frame::<2, 4>(b"second")].concat());
+
+  let mut pending: VecDeque<u8> = wire.into_iter().collect();
+  let mut decoder = Decoder::<2, 4, _, 64>::new(|buffer: &mut [u8]| -> Result<TransmissionStatus, nb::Error<Infallible>> {
+    let n = buffer.len().min(pending.len());
+    if n == 0 {
+      return Err(nb::Error::WouldBlock);
+    }
+    buffer.iter_mut().zip(pending.drain(..n)).for_each(|(b, p)| *b = p);
+    Ok(if n == buffer.len() { TransmissionStatus::Complete } else { TransmissionStatus::Partial(n) })
+  });
+  let mut out = Vec::<u8, 64>::new();
+  decoder.read(&mut out).unwrap();
+  assert_eq!(out, b"first");
+  decoder.read(&mut out).unwrap();
+  assert_eq!(out, b"second");
+  assert_eq!(decoder.read(&mut out), Err(nb::Error::WouldBlock));
+}
+
+#[test]
+fn each_failure_has_its_own_error() {
+  let mut encoder = Encoder::<1, 4, _>::new(|_: &[&[u8]]| -> Result<TransmissionStatus, nb::Error<&str>> {
+    Err(nb::Error::Other("unplugged"))
+  });
+  assert_eq!(encoder.write(&[0; 256]), Err(nb::Error::Other(DencoderError::MessageTooLong)));
+  assert_eq!(encoder.write(b"hi"), Err(nb::Error::Other(DencoderError::Transport("unplugged"))));
+
+  let mut bad_length = frame::<2, 4>(b"bad length");
+  bad_length[2] ^= 0x01;
+  let mut bad_body = frame::<2, 4>(b"bad body");
+  bad_body[7] ^= 0x01;
+  let mut decoder = Decoder::<2, 4, _, 32>::new_plain();
+  let mut out = Vec::<u8, 24>::new();
+  for (input, e) in [
+    (bad_length, DencoderError::LengthChecksum),
+    (bad_body, DencoderError::MessageChecksum),
+    (frame::<2, 4>(&[1; 25]), DencoderError::MessageTooBig),
+    (frame::<2, 4>(&[1; 24]), DencoderError::BufferFull),
+  ] {
+    decoder.add(&input[..input.len().min(32)]).unwrap(); // The head's enough to reject the big ones
+    assert_eq!(decoder.read(&mut out), Err(nb::Error::Other(e)));
+    while decoder.read(&mut out) != Err(nb::Error::WouldBlock) {}
+  }
+
+  let mut decoder = Decoder::<2, 4, _, 32>::new(|_: &mut [u8]| -> Result<TransmissionStatus, nb::Error<&str>> {
+    Err(nb::Error::Other("unplugged"))
+  });
+  assert_eq!(decoder.read(&mut out), Err(nb::Error::Other(DencoderError::Transport("unplugged"))));
+}
+
+#[test]
+fn partial_writes_resume() {
+  let msgs: [&[u8]; 2] = [b"a message longer than the sink takes at once", b"next"];
+  let mut wire = std::vec::Vec::new();
+  let mut calls = 0;
+  let mut blocked = 0;
+  {
+    // Takes up to 3 bytes a call, and nothing at all every fourth call
+    let mut trickle = trickle_sink(&mut wire, 3);
+    let mut encoder = Encoder::<2, 4, _>::new(|buffers: &[&[u8]]| -> Result<TransmissionStatus, nb::Error<Infallible>> {
+      calls += 1;
+      if calls % 4 == 0 {
+        return Err(nb::Error::WouldBlock);
+      }
+      trickle(buffers)
+    });
+    for m in msgs {
+      while let Err(e) = encoder.write(m) {
+        assert_eq!(e, nb::Error::WouldBlock);
+        blocked += 1;
+      }
+    }
+  }
+  assert_eq!(wire, [frame::<2, 4>(msgs[0]), frame::<2, 4>(msgs[1])].concat());
+  assert!(blocked >= wire.len() / 3);
+}
+
+#[test]
+fn decode_in_place_borrows_from_the_buffer() {
+  let mut buf = [&[0x13, MAGIC_BYTE, 0x00][..], &frame::<2, 4>(b"first"), &[0x77], &frame::<2, 4>(b"second")].concat();
+  let whole = buf.as_ptr_range();
+  let mut decoder = Decoder::<2, 4, _, 0>::new_plain();
+  let mut rest = &mut buf[..];
+  let mut msgs = vec![];
+  loop {
+    match decoder.decode_in_place(rest) {
+      Ok((msg, consumed)) => {
+        assert!(whole.contains(&msg.as_ptr()));
+        msgs.push(msg.to_vec());
+        rest = &mut rest[consumed..];
+      },
+      Err(InPlaceError { error: nb::Error::WouldBlock, consumed }) => {
+        assert_eq!(consumed, rest.len());
+        break;
+      },
+      Err(InPlaceError { consumed, .. }) => rest = &mut rest[consumed..],
+    }
+  }
+  assert_eq!(msgs, [b"first".to_vec(), b"second".to_vec()]);
+}
+
+#[test]
+fn decode_in_place_waits_for_the_whole_frame() {
+  let whole = [&[0x01, 0x02][..], &frame::<2, 4>(b"hello")].concat();
+  let mut decoder = Decoder::<2, 4, _, 0>::new_plain();
+  for n in 0..whole.len() {
+    let mut buf = whole[..n].to_vec();
+    let e = decoder.decode_in_place(&mut buf).unwrap_err();
+    assert_eq!(e.error, nb::Error::WouldBlock);
+    assert_eq!(e.consumed, n.min(2));
+  }
+  let mut buf = whole.clone();
+  assert_eq!(decoder.decode_in_place(&mut buf).unwrap(), (&b"hello"[..], whole.len()));
+}
+
+#[test]
+fn decode_in_place_cobs_with_fec() {
+  let mut out = vec![0u8; 1024];
+  let mut encoder = Encoder::<2, 4, _>::new_plain(&mut out).with_framing(Framing::Cobs).with_fec(2);
+  encoder.write(b"repair me").unwrap();
+  encoder.write(b"bad").unwrap();
+  encoder.write(b"fine").unwrap();
+  let n = encoder.written();
+  out.truncate(n);
+  let first_end = out.iter().position(|&b| b == 0).unwrap() + 1;
+  out[8] ^= 0x20; // One byte in the first message, which FEC repairs
+  out[first_end + 3] = 0x00; // A stray delimiter splits the second frame
+
+  let mut decoder = Decoder::<2, 4, _, 0>::new_plain().with_framing(Framing::Cobs).with_fec(2);
+  let mut rest = &mut out[..];
+  let mut results = vec![];
+  while !rest.is_empty() {
+    match decoder.decode_in_place(rest) {
+      Ok((msg, consumed)) => {
+        results.push(Ok(msg.to_vec()));
+        rest = &mut rest[consumed..];
+      },
+      Err(e) => {
+        results.push(Err(e.error));
+        rest = &mut rest[e.consumed..];
+      },
+    }
+  }
+  assert_eq!(results, [
+    Ok(b"repair me".to_vec()),
+    Err(nb::Error::Other(DencoderError::BadFraming)),
+    Err(nb::Error::Other(DencoderError::BadFraming)),
+    Ok(b"fine".to_vec()),
+  ]);
+  assert_eq!(decoder.fec_stats().corrected_frames, 1);
+}
+
+#[test]
+fn over_long_cobs_frame_is_bad_framing() {
+  // A good frame with extra bytes tacked on inside the same COBS frame
+  let mut inner = frame::<2, 4>(b"hello");
+  inner.extend_from_slice(&[1, 2, 3]);
+  let mut wire = vec![0u8];
+  let mut code = 0;
+  for &b in &inner {
+    if b == 0 {
+      wire[code] = (wire.len() - code) as u8;
+      code = wire.len();
+      wire.push(0);
+    } else {
+      wire.push(b);
+    }
+  }
+  wire[code] = (wire.len() - code) as u8;
+  wire.push(0);
+
+  let mut decoder = Decoder::<2, 4, _, 512>::new_plain().with_framing(Framing::Cobs);
+  decoder.add(&wire).unwrap();
+  let mut out = Vec::<u8, 256>::new();
+  assert_eq!(decoder.read(&mut out), Err(nb::Error::Other(DencoderError::BadFraming)));
+  assert_eq!(decoder.read(&mut out), Err(nb::Error::WouldBlock));
+
+  let mut in_place = Decoder::<2, 4, _, 0>::new_plain().with_framing(Framing::Cobs);
+  let e = in_place.decode_in_place(&mut wire.clone()).unwrap_err();
+  assert_eq!((e.error, e.consumed), (nb::Error::Other(DencoderError::BadFraming), wire.len()));
+  assert_eq!(decoder.stats().bad_framing, 1);
+  assert_eq!(in_place.stats().bad_framing, 1);
+}
+
+#[test]
+fn write_vectored_matches_write() {
+  let big: std::vec::Vec<u8> = (0..600).map(|i| (i * 13) as u8).collect();
+  let many: std::vec::Vec<&[u8]> = big.chunks(37).collect(); // More parts than go to the sink at once
+  let cases: [&[&[u8]]; 5] = [&[], &[b"", b""], &[b"head", b"", b"payload"], &[&big[..300], &big[300..]], &many];
+  for framing in [Framing::MagicByte, Framing::Cobs] {
+    for fec in [0, 3] {
+      for parts in cases {
+        let whole = parts.concat();
+        let mut expected = vec![0u8; 2048];
+        let mut encoder = Encoder::<2, 4, _>::new_plain(&mut expected).with_framing(framing).with_fec(fec);
+        encoder.write(&whole).unwrap();
+        let n = encoder.written();
+        expected.truncate(n);
+
+        let mut wire = std::vec::Vec::new();
+        {
+          let mut encoder = Encoder::<2, 4, _>::new(trickle_sink(&mut wire, 5)).with_framing(framing).with_fec(fec);
+          while let Err(e) = encoder.write_vectored(parts) {
+            assert_eq!(e, nb::Error::WouldBlock);
+          }
+        }
+        assert_eq!(wire, expected, "{:?} fec {} parts {}", framing, fec, parts.len());
+      }
+    }
+  }
+}
+
+#[test]
+fn streamed_frame_matches_write() {
+  let big: std::vec::Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect(); // Zeros here and there, for COBS
+  for framing in [Framing::MagicByte, Framing::Cobs] {
+    for fec in [0, 3] {
+      for len in [0, 1, 248, 249, 1000] {
+        for chunk in [1, 100, 1000] {
+          let msg = &big[..len];
+          let mut expected = vec![0u8; 2048];
+          let mut encoder = Encoder::<2, 4, _>::new_plain(&mut expected).with_framing(framing).with_fec(fec);
+          encoder.write(msg).unwrap();
+          let n = encoder.written();
+          expected.truncate(n);
+
+          let mut wire = std::vec::Vec::new();
+          {
+            let mut encoder = Encoder::<2, 4, _>::new(trickle_sink(&mut wire, 5)).with_framing(framing).with_fec(fec);
+            let mut frame = encoder.begin(len).unwrap();
+            for c in msg.chunks(chunk) {
+              nb::block!(frame.write_chunk(c)).unwrap();
+            }
+            assert_eq!(frame.remaining(), 0);
+            nb::block!(frame.finish()).unwrap();
+          }
+          assert_eq!(wire, expected, "{:?} fec {} len {} chunk {}", framing, fec, len, chunk);
+        }
+      }
+    }
+  }
+}
+
+#[test]
+fn streamed_cobs_frame_without_early_zero() {
+  // Nothing flushes the COBS run until well into the message, so the head sits in it across chunks
+  let msg: std::vec::Vec<u8> = (1..=200).collect();
+  for len in [5, 200] {
+    let msg = &msg[..len];
+    let expected = cobs_frame::<1, 4>(msg);
+    for chunk in [1, len] {
+      let mut out = vec![0u8; 512];
+      let mut encoder = Encoder::<1, 4, _>::new_plain(&mut out).with_framing(Framing::Cobs);
+      {
+        let mut frame = encoder.begin(len).unwrap();
+        for c in msg.chunks(chunk) {
+          frame.write_chunk(c).unwrap();
+        }
+        frame.finish().unwrap();
+      }
+      let n = encoder.written();
+      assert_eq!(out[..n], expected[..], "len {} chunk {}", len, chunk);
+      assert_eq!(decode_all_framed::<1, 4>(Framing::Cobs, &out[..n], n), [msg]);
+    }
+  }
+}
+
+#[test]
+fn streamed_frame_checks_length() {
+  let mut out = vec![0u8; 64];
+  let mut encoder = Encoder::<1, 4, _>::new_plain(&mut out);
+  assert_eq!(encoder.begin(256).err(), Some(DencoderError::MessageTooLong));
+  {
+    let mut frame = encoder.begin(4).unwrap();
+    frame.write_chunk(b"abc").unwrap();
+    assert_eq!(frame.write_chunk(b"de"), Err(nb::Error::Other(DencoderError::BadStream)));
+    assert_eq!(frame.finish(), Err(nb::Error::Other(DencoderError::BadStream)));
+    frame.write_chunk(b"d").unwrap();
+    frame.finish().unwrap();
+    assert_eq!(frame.finish(), Err(nb::Error::Other(DencoderError::BadStream)));
+  }
+  let n = encoder.written();
+  assert_eq!(out[..n], frame::<1, 4>(b"abcd")[..]);
+}
+
+#[test]
+fn encoder_stats() {
+  let mut encoder = Encoder::<2, 4, _>::new(VecSink::<40>::new()).with_rate_meter(RateMeter::default());
+  encoder.write(&[1; 10]).unwrap();
+  assert_eq!(encoder.write(&[2; 30]), Err(nb::Error::WouldBlock));
+  encoder.sink_mut().take();
+  encoder.write(&[2; 30]).unwrap();
+  encoder.begin(3).unwrap().write_chunk(b"abc").unwrap(); // Abandoned
+  assert_eq!(encoder.write(&[0; 70000]), Err(nb::Error::Other(DencoderError::MessageTooLong)));
+  let stats = encoder.stats();
+  assert_eq!(stats, EncoderStats { frames: 2, bytes: calcMsgSize(2, 4, 10) as u64 + calcMsgSize(2, 4, 30) as u64 + 8, partial_writes: 1, oversize_drops: 1, transport_errors: 0 });
+  assert_eq!(encoder.rate_meter_mut().unwrap().count, 2);
+}
+
+#[test]
+fn decoder_stats() {
+  let mut bad_length = frame::<2, 4>(b"bad length");
+  bad_length[2] ^= 0x01;
+  let mut bad_body = frame::<2, 4>(b"bad body");
+  bad_body[7] ^= 0x01;
+  let input = [&b"noise"[..], &frame::<2, 4>(b"one"), &bad_length, &bad_body, &frame::<2, 4>(&[7; 40]), &frame::<2, 4>(b"two")].concat();
+
+  let mut decoder = Decoder::<2, 4, _, 512>::new_plain().with_rate_meter(RateMeter::default());
+  decoder.add(&input).unwrap();
+  let mut out = Vec::<u8, 16>::new();
+  let mut got = vec![];
+  loop {
+    match decoder.read(&mut out) {
+      Ok(()) => got.push(out.to_vec()),
+      Err(nb::Error::WouldBlock) => break,
+      Err(nb::Error::Other(_)) => (),
+    }
+  }
+  assert_eq!(got, [b"one".to_vec(), b"two".to_vec()]);
+  let stats = decoder.stats();
+  assert_eq!(stats.frames, 2);
+  assert_eq!(stats.bytes, input.len() as u64);
+  assert_eq!((stats.length_checksum_failures, stats.message_checksum_failures, stats.oversize_drops), (1, 1, 1));
+  assert_eq!(stats.resyncs, 3);
+  assert!(stats.skipped_bytes >= 5 + 3);
+  assert_eq!(decoder.rate_meter_mut().unwrap().count, 2);
+
+  let mut cobs = Decoder::<2, 4, _, 512>::new_plain().with_framing(Framing::Cobs);
+  let mut garbled = cobs_frame::<2, 4>(b"x");
+  garbled[0] = 0x40;
+  cobs.add(&garbled).unwrap();
+  assert!(cobs.read(&mut out).is_err());
+  assert_eq!((cobs.stats().bad_framing, cobs.stats().resyncs), (1, 0));
+
+  let mut buf = frame::<2, 4>(b"in place");
+  let mut in_place = Decoder::<2, 4, BufferSource<0>, 0>::new_plain();
+  in_place.decode_in_place(&mut buf).unwrap();
+  assert_eq!(in_place.stats().frames, 1);
+}
@@ -0,0 +1,60 @@
+use erhannis_misc::dencoder::fec::{correct, correct_blocks, encoded_len, generator, parity, BLOCK_BYTES};
+
+/// Data followed by its parity
+fn codeword(data: &[u8], parity_bytes: usize) -> Vec<u8> {
+  let mut p = vec![0u8; parity_bytes];
+  parity(&generator(parity_bytes), &[data], &mut p);
+  [data, &p[..]].concat()
+}
+
+#[test]
+fn known_codeword() {
+  // Worked out separately, by long division by (x - 1)(x - 2)(x - 4)(x - 8) over GF(2^8)/0x11D
+  assert_eq!(codeword(b"hello world", 4)[11..], [0x45, 0x3c, 0x17, 0x4e]);
+}
+
+#[test]
+fn repairs_up_to_half_the_parity() {
+  let data: Vec<u8> = (0..200u32).map(|i| (i * 37 + 11) as u8).collect();
+  for parity_bytes in [2, 8, 16] {
+    let clean = codeword(&data, parity_bytes);
+    let t = parity_bytes / 2;
+    let mut block = clean.clone();
+    assert_eq!(correct(&mut block, parity_bytes), Some(0));
+    for start in [0, 3, clean.len() - t] {
+      // Errors in data, spread out, and in the parity
+      let mut block = clean.clone();
+      for k in 0..t {
+        let pos = (start + k * 7) % clean.len();
+        block[pos] ^= 0x5A ^ k as u8;
+      }
+      assert_eq!(correct(&mut block, parity_bytes), Some(t), "parity {} start {}", parity_bytes, start);
+      assert_eq!(block, clean);
+    }
+    // One too many; this code happens to detect it rather than miscorrect
+    let mut block = clean.clone();
+    for k in 0..=t {
+      block[k * 3] ^= 0xFF;
+    }
+    let damaged = block.clone();
+    assert_eq!(correct(&mut block, parity_bytes), None, "parity {}", parity_bytes);
+    assert_eq!(block, damaged); // Left alone
+  }
+}
+
+#[test]
+fn blocks_pack_down() {
+  let parity_bytes = 6;
+  let data: Vec<u8> = (0..600u32).map(|i| (i % 251) as u8).collect();
+  let mut buf = vec![];
+  for chunk in data.chunks(BLOCK_BYTES - parity_bytes) {
+    buf.extend(codeword(chunk, parity_bytes));
+  }
+  assert_eq!(buf.len(), encoded_len(data.len(), parity_bytes));
+  buf[0] ^= 1;
+  buf[300] ^= 2;
+  buf[301] ^= 4;
+  *buf.last_mut().unwrap() ^= 8;
+  assert_eq!(correct_blocks(&mut buf, parity_bytes), Some(4));
+  assert_eq!(buf[..data.len()], data[..]);
+}
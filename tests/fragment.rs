@@ -0,0 +1,158 @@
+use std::convert::Infallible;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use erhannis_misc::dencoder::fragment::{FragmentStats, Fragmenter, Reassembler, FRAGMENT_HEADER_BYTES};
+use erhannis_misc::dencoder::transport::{BufferSource, FnSink};
+use erhannis_misc::dencoder::{Decoder, DencoderError, Encoder, TransmissionStatus};
+use heapless::Vec;
+
+/// Frames so far, and the one being written.
+type Frames = (std::vec::Vec<std::vec::Vec<u8>>, std::vec::Vec<u8>);
+
+fn tx(frames: &mut Frames, buffers: &[&[u8]]) -> Result<TransmissionStatus, nb::Error<Infallible>> {
+  for b in buffers {
+    frames.1.extend_from_slice(b);
+  }
+  Ok(TransmissionStatus::Complete)
+}
+
+fn end_frame(frames: &mut Frames) {
+  let f = std::mem::take(&mut frames.1);
+  frames.0.push(f);
+}
+
+// One-byte length prefix, so 255-byte dencoder messages at most
+type Sender = Fragmenter<Encoder<1, 4, FnSink<Frames, Infallible>>, 64>;
+type Receiver = Reassembler<Decoder<1, 4, BufferSource<512>, 512>, 64, 2000, 2>;
+
+fn sender() -> Sender {
+  Fragmenter::new(Encoder::new(FnSink::new((vec![], vec![]), None, tx, Some(end_frame))))
+}
+
+/// Each of the fragments `msgs` come to, as a separate frame.
+fn fragments(sender: &mut Sender, msgs: &[&[u8]]) -> std::vec::Vec<std::vec::Vec<u8>> {
+  for m in msgs {
+    sender.send(m).unwrap();
+  }
+  std::mem::take(&mut sender.encoder_mut().sink_mut().state.0)
+}
+
+static START: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Feeds `frames` in at `now`, returning the messages that come out.
+fn deliver_at(receiver: &mut Receiver, frames: &[std::vec::Vec<u8>], now: Instant) -> std::vec::Vec<std::vec::Vec<u8>> {
+  let mut got = vec![];
+  let mut buf = Vec::<u8, 2000>::new();
+  for f in frames {
+    receiver.decoder_mut().add(f).unwrap();
+    while receiver.poll_at(&mut buf, now).is_ok() {
+      got.push(buf.to_vec());
+    }
+  }
+  got
+}
+
+/// All at the same moment, so nothing times out unless a test moves the clock on itself.
+fn deliver(receiver: &mut Receiver, frames: &[std::vec::Vec<u8>]) -> std::vec::Vec<std::vec::Vec<u8>> {
+  deliver_at(receiver, frames, *START)
+}
+
+fn message(len: usize, seed: u8) -> std::vec::Vec<u8> {
+  (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+}
+
+#[test]
+fn round_trip() {
+  let big = message(2000, 1);
+  let small = message(10, 2);
+  let exact = message(64 - FRAGMENT_HEADER_BYTES, 3);
+  let msgs: [&[u8]; 4] = [&big, &small, &[], &exact];
+  let mut sender = sender();
+  let frames = fragments(&mut sender, &msgs);
+  assert_eq!(frames.len(), 2000usize.div_ceil(64 - FRAGMENT_HEADER_BYTES) + 3);
+
+  let mut receiver = Reassembler::new(Decoder::new_plain());
+  assert_eq!(deliver(&mut receiver, &frames), msgs.map(|m| m.to_vec()));
+  assert_eq!(receiver.stats(), FragmentStats::default());
+  assert_eq!(receiver.in_progress(), 0);
+}
+
+#[test]
+fn out_of_order_and_duplicated() {
+  let a = message(300, 4);
+  let b = message(200, 5);
+  let mut sender = sender();
+  let fa = fragments(&mut sender, &[&a]);
+  let fb = fragments(&mut sender, &[&b]);
+  // Interleaved and backwards, with repeats, and a repeat of a message already delivered
+  let mut frames = vec![];
+  for i in (0..fa.len()).rev() {
+    frames.push(fa[i].clone());
+    if let Some(f) = fb.get(i) {
+      frames.push(f.clone());
+      frames.push(f.clone());
+    }
+  }
+  frames.push(fb[0].clone());
+
+  let mut receiver = Reassembler::new(Decoder::new_plain());
+  assert_eq!(deliver(&mut receiver, &frames), vec![a.clone(), b.clone()]);
+  assert_eq!(receiver.stats().duplicates as usize, fb.len() + 1);
+  assert_eq!(receiver.stats().abandoned, 0);
+}
+
+#[test]
+fn lost_fragment_times_out() {
+  let a = message(300, 6);
+  let b = message(100, 7);
+  let mut sender = sender();
+  let mut frames = fragments(&mut sender, &[&a, &b]);
+  frames.remove(2);
+
+  let start = *START;
+  let mut receiver = Reassembler::new(Decoder::new_plain()).with_timeout(Duration::from_millis(20));
+  assert_eq!(deliver_at(&mut receiver, &frames, start), vec![b.clone()]);
+  assert_eq!(receiver.in_progress(), 1);
+  let mut buf = Vec::<u8, 2000>::new();
+  assert_eq!(receiver.poll_at(&mut buf, start + Duration::from_millis(19)), Err(nb::Error::WouldBlock));
+  assert_eq!(receiver.in_progress(), 1);
+  assert_eq!(receiver.poll_at(&mut buf, start + Duration::from_millis(20)), Err(nb::Error::WouldBlock));
+  assert_eq!(receiver.in_progress(), 0);
+  assert_eq!(receiver.stats().abandoned, 1);
+}
+
+#[test]
+fn too_many_slots_pushes_out_the_oldest() {
+  let msgs: std::vec::Vec<std::vec::Vec<u8>> = (0..3).map(|i| message(100, i)).collect();
+  let mut sender = sender();
+  let frames: std::vec::Vec<_> = msgs.iter().map(|m| fragments(&mut sender, &[m])).collect();
+
+  // The first fragment of each, then the rest of the last two; only 2 SLOTS, so the first message got pushed out
+  let mut receiver = Reassembler::new(Decoder::new_plain());
+  let firsts: std::vec::Vec<_> = frames.iter().map(|f| f[0].clone()).collect();
+  let rests: std::vec::Vec<_> = frames[1..].iter().flat_map(|f| f[1..].iter().cloned()).collect();
+  assert!(deliver(&mut receiver, &firsts).is_empty());
+  assert_eq!(deliver(&mut receiver, &rests), vec![msgs[1].clone(), msgs[2].clone()]);
+  assert_eq!(receiver.stats().abandoned, 1);
+}
+
+#[test]
+fn damaged_frames_are_counted() {
+  let a = message(100, 8);
+  let mut sender = sender();
+  let mut frames = fragments(&mut sender, &[&a, &a]);
+  frames[0][10] ^= 0x40;
+
+  let mut receiver = Reassembler::new(Decoder::new_plain());
+  assert_eq!(deliver(&mut receiver, &frames), vec![a.clone()]);
+  assert_eq!(receiver.stats().bad_frames, 1);
+}
+
+#[test]
+fn message_too_long() {
+  let mut sender = sender();
+  let too_many = message(255 * (64 - FRAGMENT_HEADER_BYTES) + 1, 9);
+  assert_eq!(sender.send(&too_many), Err(nb::Error::Other(DencoderError::MessageTooLong)));
+  assert!(fragments(&mut sender, &[]).is_empty());
+}
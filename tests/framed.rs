@@ -0,0 +1,76 @@
+use std::io::{self, Read};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use erhannis_misc::dencoder::framed::{FramedReader, FramedWriter};
+use erhannis_misc::dencoder::{calcMsgSize, DencoderError, Encoder, Framing};
+
+/// Hands out at most one byte per read, like a slow serial port.
+struct Trickle<'a>(&'a [u8]);
+
+impl Read for Trickle<'_> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = buf.len().min(1).min(self.0.len());
+    buf[..n].copy_from_slice(&self.0[..n]);
+    self.0 = &self.0[n..];
+    Ok(n)
+  }
+}
+
+#[test]
+fn writer_matches_write_plain() {
+  let mut writer = FramedWriter::<2, 4, _>::new(std::vec::Vec::new());
+  writer.write_frame(b"hello").unwrap();
+  writer.write_frame(b"").unwrap();
+
+  let mut expected = vec![0u8; calcMsgSize(2, 4, 5)];
+  Encoder::<2, 4, ()>::write_plain(b"hello", &mut expected).unwrap();
+  let mut empty = vec![0u8; calcMsgSize(2, 4, 0)];
+  Encoder::<2, 4, ()>::write_plain(b"", &mut empty).unwrap();
+  expected.extend_from_slice(&empty);
+  assert_eq!(writer.into_inner(), expected);
+
+  let mut writer = FramedWriter::<1, 4, _>::new(std::vec::Vec::new());
+  let e = writer.write_frame(&[0; 256]).unwrap_err();
+  assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn reader_skips_damage_and_stops_at_eof() {
+  let mut writer = FramedWriter::<2, 4, _>::new(std::vec::Vec::new()).with_framing(Framing::Cobs);
+  for m in [&b"one"[..], b"two", b"three"] {
+    writer.write_frame(m).unwrap();
+  }
+  let mut wire = writer.into_inner();
+  wire[21] ^= 0x01; // Inside "two"
+
+  let mut reader = FramedReader::<2, 4, _, 256>::new(Trickle(&wire)).with_framing(Framing::Cobs);
+  assert_eq!(reader.read_frame().unwrap(), b"one");
+  let e = reader.read_frame().unwrap_err();
+  assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+  assert_eq!(e.get_ref().unwrap().downcast_ref::<DencoderError<std::convert::Infallible>>(), Some(&DencoderError::MessageChecksum));
+  assert_eq!(reader.read_frame().unwrap(), b"three");
+  assert_eq!(reader.read_frame().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn over_tcp() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let msgs: std::vec::Vec<std::vec::Vec<u8>> = (0..50).map(|i| vec![i as u8; i * 20]).collect();
+
+  let sent = msgs.clone();
+  let sender = thread::spawn(move || {
+    let mut writer = FramedWriter::<2, 4, _>::new(TcpStream::connect(addr).unwrap());
+    for m in &sent {
+      writer.write_frame(m).unwrap();
+    }
+  });
+  let (stream, _) = listener.accept().unwrap();
+  let mut reader = FramedReader::<2, 4, _, 2048>::new(stream);
+  for m in &msgs {
+    assert_eq!(&reader.read_frame().unwrap(), m);
+  }
+  assert_eq!(reader.read_frame().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+  sender.join().unwrap();
+}
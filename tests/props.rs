@@ -0,0 +1,180 @@
+// Property tests for the Decoder, over every LEN_PREFIX_BYTES/CHECKSUM_BYTES combination.
+// Whatever the input, `read` mustn't panic; with good enough checksums it must also find every
+// intact frame.  The fuzz targets in fuzz/ go after the same things with coverage guidance.
+// For a longer run than the default, `PROPTEST_CASES=1000 cargo test --release --test props`.
+
+use std::collections::BTreeMap;
+
+use erhannis_misc::dencoder::transport::BufferSource;
+use erhannis_misc::dencoder::{calc_cobs_msg_size, calcMsgSize, Decoder, DecoderT, Encoder, Framing};
+use heapless::Vec;
+use proptest::prelude::*;
+
+const BUF_SIZE: usize = 1024;
+const CAPACITY: usize = 256;
+/// Zeros after the frames, enough to finish off any bogus frame still waiting on its body.
+const TAIL: usize = 512;
+
+/// Calls `$f::<L, C>(args)` for every L in 1..=8 and C in 0..=32.
+macro_rules! all_formats {
+  ($f:ident $args:tt) => {
+    all_formats!(@l $f $args [1 2 3 4 5 6 7 8])
+  };
+  (@l $f:ident $args:tt [$($l:literal)*]) => {
+    $( all_formats!(@c $f $args $l [0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32]); )*
+  };
+  (@c $f:ident $args:tt $l:literal [$($c:literal)*]) => {
+    $( all_formats!(@call $f $args $l $c); )*
+  };
+  (@call $f:ident ($($arg:expr),*) $l:literal $c:literal) => {
+    $f::<$l, $c>($($arg),*)
+  };
+}
+
+fn frame<const L: usize, const C: usize>(framing: Framing, msg: &[u8]) -> std::vec::Vec<u8> {
+  match framing {
+    Framing::MagicByte => {
+      let mut out = vec![0u8; calcMsgSize(L, C, msg.len())];
+      Encoder::<L, C, ()>::write_plain(msg, &mut out).unwrap();
+      out
+    },
+    Framing::Cobs => {
+      let mut out = vec![0u8; calc_cobs_msg_size(L, C, msg.len())];
+      let n = Encoder::<L, C, ()>::write_plain_cobs(msg, &mut out).unwrap();
+      out.truncate(n);
+      out
+    },
+  }
+}
+
+/// Feeds `input` to a fresh Decoder in pieces of the given `chunks` sizes (cycling; 1 is byte at a
+/// time), reading everything it can after each, and returns the messages it produced.
+fn decode<const L: usize, const C: usize>(framing: Framing, input: &[u8], chunks: &[usize]) -> std::vec::Vec<std::vec::Vec<u8>> {
+  let mut decoder = Decoder::<L, C, BufferSource<BUF_SIZE>, BUF_SIZE>::new_plain().with_framing(framing);
+  let mut out = Vec::<u8, CAPACITY>::new();
+  let mut msgs = vec![];
+  let mut rest = input;
+  for &chunk in chunks.iter().cycle() {
+    if rest.is_empty() {
+      break;
+    }
+    let (c, r) = rest.split_at(chunk.min(rest.len()));
+    rest = r;
+    decoder.add(c).unwrap();
+    loop {
+      match decoder.read(&mut out) {
+        Ok(()) => msgs.push(out.to_vec()),
+        Err(nb::Error::WouldBlock) => break,
+        Err(nb::Error::Other(_)) => (),
+      }
+    }
+  }
+  msgs
+}
+
+/// Whether a bogus frame could plausibly get past the checksums, so only panics count as failures.
+/// (With 4 bytes of message checksum, a bad frame gets through one time in 2^32.)
+fn weak<const C: usize>() -> bool {
+  C < 4
+}
+
+fn round_trip<const L: usize, const C: usize>(framing: Framing, msgs: &[std::vec::Vec<u8>], chunks: &[usize]) {
+  let input: std::vec::Vec<u8> = msgs.iter().flat_map(|m| frame::<L, C>(framing, m)).collect();
+  assert_eq!(decode::<L, C>(framing, &input, &[1]), msgs, "{:?} L {} C {} byte at a time", framing, L, C);
+  assert_eq!(decode::<L, C>(framing, &input, chunks), msgs, "{:?} L {} C {} chunks {:?}", framing, L, C, chunks);
+}
+
+fn resyncs_after_junk<const L: usize, const C: usize>(noise: &[u8], cut: usize, msgs: &[std::vec::Vec<u8>]) {
+  let mut input = noise.to_vec();
+  let truncated = frame::<L, C>(Framing::MagicByte, &[0x55; 100]);
+  input.extend_from_slice(&truncated[..cut.min(truncated.len() - 1)]);
+  for m in msgs {
+    input.extend(frame::<L, C>(Framing::MagicByte, m));
+  }
+  input.extend_from_slice(&[0; TAIL]);
+  let got = decode::<L, C>(Framing::MagicByte, &input, &[1]);
+  if !weak::<C>() {
+    assert_eq!(got, msgs, "L {} C {} noise {:02x?} cut {}", L, C, noise, cut);
+  }
+}
+
+fn survives_corruption<const L: usize, const C: usize>(framing: Framing, msgs: &[std::vec::Vec<u8>], damage: &BTreeMap<usize, u8>) {
+  let frames: std::vec::Vec<std::vec::Vec<u8>> = msgs.iter().map(|m| frame::<L, C>(framing, m)).collect();
+  let mut input = frames.concat();
+  for (&i, &x) in damage.range(..input.len()) {
+    input[i] ^= x;
+  }
+  // A frame gets through if it wasn't hit, and (with COBS) there's still a delimiter right before it
+  let mut start = 0;
+  let mut expected = vec![];
+  for (f, m) in frames.iter().zip(msgs) {
+    let end = start + f.len();
+    let delimited = framing == Framing::MagicByte || start == 0 || input[start - 1] == 0;
+    if delimited && damage.range(start..end).next().is_none() {
+      expected.push(m.clone());
+    }
+    start = end;
+  }
+  input.extend_from_slice(&[0; TAIL]);
+  let got = decode::<L, C>(framing, &input, &[1]);
+  if !weak::<C>() {
+    assert_eq!(got, expected, "{:?} L {} C {} damage {:?}", framing, L, C, damage);
+  }
+}
+
+fn noise_never_panics<const L: usize, const C: usize>(framing: Framing, noise: &[u8], chunks: &[usize]) {
+  decode::<L, C>(framing, noise, chunks);
+  let mut buf = noise.to_vec();
+  let mut in_place = Decoder::<L, C, BufferSource<0>, 0>::new_plain().with_framing(framing);
+  let mut at = 0;
+  while at < buf.len() {
+    match in_place.decode_in_place(&mut buf[at..]) {
+      Ok((_, consumed)) => at += consumed,
+      Err(e) if e.error == nb::Error::WouldBlock => break,
+      Err(e) => at += e.consumed,
+    }
+  }
+}
+
+/// PROPTEST_CASES if it's set (`with_cases` would override it), else just a few, since each case runs every format.
+fn cases() -> u32 {
+  std::env::var("PROPTEST_CASES").ok().and_then(|c| c.parse().ok()).unwrap_or(8)
+}
+
+fn framing() -> impl Strategy<Value = Framing> {
+  prop_oneof![Just(Framing::MagicByte), Just(Framing::Cobs)]
+}
+
+fn messages() -> impl Strategy<Value = std::vec::Vec<std::vec::Vec<u8>>> {
+  // Plenty of magic bytes and zeros, since those are what the framing cares about
+  let byte = prop_oneof![Just(0xA9u8), Just(0xA5), Just(0x00), any::<u8>()];
+  prop::collection::vec(prop::collection::vec(byte, 0..200), 0..5)
+}
+
+fn chunks() -> impl Strategy<Value = std::vec::Vec<usize>> {
+  prop::collection::vec(1..64usize, 1..8)
+}
+
+proptest! {
+  #![proptest_config(ProptestConfig { cases: cases(), ..ProptestConfig::default() })]
+
+  #[test]
+  fn every_format_round_trips(framing in framing(), msgs in messages(), chunks in chunks()) {
+    all_formats!(round_trip(framing, &msgs, &chunks));
+  }
+
+  #[test]
+  fn every_format_resyncs_after_junk(noise in prop::collection::vec(any::<u8>(), 0..64), cut in 0..150usize, msgs in messages()) {
+    all_formats!(resyncs_after_junk(&noise, cut, &msgs));
+  }
+
+  #[test]
+  fn every_format_survives_corruption(framing in framing(), msgs in messages(), damage in prop::collection::btree_map(0..1000usize, 1..=255u8, 1..6)) {
+    all_formats!(survives_corruption(framing, &msgs, &damage));
+  }
+
+  #[test]
+  fn every_format_survives_noise(framing in framing(), noise in prop::collection::vec(any::<u8>(), 0..1024), chunks in chunks()) {
+    all_formats!(noise_never_panics(framing, &noise, &chunks));
+  }
+}
@@ -0,0 +1,136 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::rc::Rc;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use erhannis_misc::dencoder::reliable::{ReliableLink, ReliableStats};
+use erhannis_misc::dencoder::transport::{FnSink, FnSource};
+use erhannis_misc::dencoder::{Decoder, Encoder, TransmissionStatus};
+use heapless::Vec;
+
+/// One direction of a link.  Loses about one in `drop_one_in` frames, if set, pseudo-randomly
+/// (a fixed pattern can line up with the retransmissions and lose the same frame forever).
+#[derive(Default)]
+struct Wire {
+  bytes: VecDeque<u8>,
+  rng: u32,
+  drop_one_in: Option<u32>,
+}
+
+type WireRef = Rc<RefCell<Wire>>;
+
+fn tx(wire: &mut WireRef, buffers: &[&[u8]]) -> Result<TransmissionStatus, nb::Error<Infallible>> {
+  let mut w = wire.borrow_mut();
+  w.rng = w.rng.wrapping_mul(1664525).wrapping_add(1013904223);
+  let r = w.rng >> 16;
+  if w.drop_one_in.is_some_and(|n| r.is_multiple_of(n)) {
+    return Ok(TransmissionStatus::Complete);
+  }
+  for b in buffers {
+    w.bytes.extend(b.iter());
+  }
+  Ok(TransmissionStatus::Complete)
+}
+
+fn rx(wire: &mut WireRef, buffer: &mut [u8]) -> Result<TransmissionStatus, nb::Error<Infallible>> {
+  let mut w = wire.borrow_mut();
+  let n = buffer.len().min(w.bytes.len());
+  if n == 0 {
+    return Err(nb::Error::WouldBlock);
+  }
+  for b in buffer[..n].iter_mut() {
+    *b = w.bytes.pop_front().unwrap();
+  }
+  Ok(if n == buffer.len() { TransmissionStatus::Complete } else { TransmissionStatus::Partial(n) })
+}
+
+type Link = ReliableLink<Encoder<2, 4, FnSink<WireRef, Infallible>>, Decoder<2, 4, FnSource<WireRef, Infallible>, 128>, 64, 4>;
+
+fn link(out: &WireRef, inp: &WireRef) -> Link {
+  ReliableLink::new(Encoder::new(FnSink::new(out.clone(), None, tx, None)), Decoder::new(FnSource::new(inp.clone(), None, rx, None)))
+    .with_timeout(Duration::from_millis(5))
+}
+
+/// Where the simulated clock starts; it only moves when a test moves it.
+static START: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Sends `count` messages a -> b, polling both ends a simulated 50us apart, and returns what b got.
+fn exchange(a: &mut Link, b: &mut Link, count: usize) -> std::vec::Vec<std::vec::Vec<u8>> {
+  let mut got = vec![];
+  let mut next = 0;
+  let mut buf = Vec::<u8, 64>::new();
+  let mut now = *START;
+  while now < *START + Duration::from_secs(20) {
+    while next < count && a.send(&[next as u8; 10]).is_ok() {
+      next += 1;
+    }
+    if a.poll_at(&mut buf, now).is_ok() {
+      panic!("nothing should be coming b -> a");
+    }
+    while b.poll_at(&mut buf, now).is_ok() {
+      got.push(buf.to_vec());
+    }
+    if got.len() == count && a.unacked() == 0 {
+      break;
+    }
+    now += Duration::from_micros(50);
+  }
+  got
+}
+
+fn check(got: std::vec::Vec<std::vec::Vec<u8>>, count: usize) {
+  let expected: std::vec::Vec<_> = (0..count).map(|i| vec![i as u8; 10]).collect();
+  assert!(got == expected, "got {} messages, first wrong one {:?}", got.len(), got.iter().zip(&expected).position(|(g, e)| g != e));
+}
+
+#[test]
+fn clean_link_delivers_in_order() {
+  let (ab, ba) = (WireRef::default(), WireRef::default());
+  let (mut a, mut b) = (link(&ab, &ba), link(&ba, &ab));
+  check(exchange(&mut a, &mut b, 20), 20);
+  assert_eq!(a.stats(), ReliableStats::default());
+  assert_eq!(b.stats(), ReliableStats::default());
+}
+
+#[test]
+fn lost_data_is_resent() {
+  let (ab, ba) = (WireRef::default(), WireRef::default());
+  ab.borrow_mut().drop_one_in = Some(3);
+  let (mut a, mut b) = (link(&ab, &ba), link(&ba, &ab));
+  // More than 256, so seqs wrap
+  check(exchange(&mut a, &mut b, 300), 300);
+  assert!(a.stats().retransmissions > 0);
+}
+
+#[test]
+fn lost_acks_are_survived() {
+  let (ab, ba) = (WireRef::default(), WireRef::default());
+  ba.borrow_mut().drop_one_in = Some(2);
+  let (mut a, mut b) = (link(&ab, &ba), link(&ba, &ab));
+  check(exchange(&mut a, &mut b, 30), 30);
+}
+
+#[test]
+fn damaged_frames_are_resent() {
+  let (ab, ba) = (WireRef::default(), WireRef::default());
+  let (mut a, mut b) = (link(&ab, &ba), link(&ba, &ab));
+  a.send(b"first").unwrap();
+  let mut buf = Vec::<u8, 64>::new();
+  let mut now = *START;
+  assert_eq!(a.poll_at(&mut buf, now), Err(nb::Error::WouldBlock));
+  let n = ab.borrow().bytes.len();
+  ab.borrow_mut().bytes[n - 1] ^= 0xFF; // Message checksum
+  assert_eq!(b.poll_at(&mut buf, now), Err(nb::Error::WouldBlock));
+  assert_eq!(b.stats().bad_frames, 1);
+  // Nothing's resent until the 5ms timeout
+  now += Duration::from_millis(4);
+  let _ = a.poll_at(&mut buf, now);
+  assert_eq!(b.poll_at(&mut buf, now), Err(nb::Error::WouldBlock));
+  now += Duration::from_millis(1);
+  let _ = a.poll_at(&mut buf, now);
+  b.poll_at(&mut buf, now).unwrap();
+  assert_eq!(&buf, b"first");
+  assert_eq!(a.stats().retransmissions, 1);
+}
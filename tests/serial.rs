@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+
+use embedded_io::{ErrorKind, ErrorType, Read, ReadReady, Write};
+use erhannis_misc::dencoder::serial::{SerialSink, SerialSource};
+use erhannis_misc::dencoder::transport::FrameSource;
+use erhannis_misc::dencoder::{calcMsgSize, Decoder, DecoderT, DencoderError, Encoder, EncoderT, TransmissionStatus};
+use heapless::Vec;
+
+#[derive(Debug, PartialEq)]
+struct Unplugged;
+
+impl core::fmt::Display for Unplugged {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "unplugged")
+  }
+}
+
+impl core::error::Error for Unplugged {}
+
+impl embedded_io::Error for Unplugged {
+  fn kind(&self) -> ErrorKind {
+    ErrorKind::NotConnected
+  }
+}
+
+/// A UART with a receive FIFO we fill by hand, which hands out at most `max_read` bytes per read,
+/// and a transmit line that takes at most `max_write` bytes per write.
+struct MockSerial {
+  received: VecDeque<u8>,
+  sent: std::vec::Vec<u8>,
+  max_read: usize,
+  max_write: usize,
+  unplugged: bool,
+  unplug_after_reads: Option<usize>,
+}
+
+impl MockSerial {
+  fn new(max_read: usize, max_write: usize) -> Self {
+    MockSerial { received: VecDeque::new(), sent: vec![], max_read, max_write, unplugged: false, unplug_after_reads: None }
+  }
+}
+
+impl ErrorType for MockSerial {
+  type Error = Unplugged;
+}
+
+impl Read for MockSerial {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, Unplugged> {
+    match self.unplug_after_reads.as_mut() {
+      Some(0) => self.unplugged = true,
+      Some(n) => *n -= 1,
+      None => (),
+    };
+    if self.unplugged {
+      return Err(Unplugged);
+    }
+    assert!(!self.received.is_empty(), "read would block");
+    let n = buf.len().min(self.max_read).min(self.received.len());
+    buf.iter_mut().zip(self.received.drain(..n)).for_each(|(b, r)| *b = r);
+    Ok(n)
+  }
+}
+
+impl ReadReady for MockSerial {
+  fn read_ready(&mut self) -> Result<bool, Unplugged> {
+    Ok(self.unplugged || !self.received.is_empty())
+  }
+}
+
+impl Write for MockSerial {
+  fn write(&mut self, buf: &[u8]) -> Result<usize, Unplugged> {
+    if self.unplugged {
+      return Err(Unplugged);
+    }
+    let n = buf.len().min(self.max_write);
+    self.sent.extend_from_slice(&buf[..n]);
+    Ok(n)
+  }
+
+  fn flush(&mut self) -> Result<(), Unplugged> {
+    Ok(())
+  }
+}
+
+#[test]
+fn encoder_writes_whole_frames() {
+  let mut encoder = Encoder::<2, 4, _>::new(SerialSink::new(MockSerial::new(1, 3)));
+  encoder.write(b"hello").unwrap();
+  encoder.write(b"").unwrap();
+
+  let mut expected = vec![0u8; calcMsgSize(2, 4, 5)];
+  Encoder::<2, 4, ()>::write_plain(b"hello", &mut expected).unwrap();
+  let mut empty = vec![0u8; calcMsgSize(2, 4, 0)];
+  Encoder::<2, 4, ()>::write_plain(b"", &mut empty).unwrap();
+  expected.extend_from_slice(&empty);
+  assert_eq!(encoder.sink().inner().sent, expected);
+}
+
+#[test]
+fn decoder_reads_as_bytes_arrive() {
+  let mut encoder = Encoder::<2, 4, _>::new(SerialSink::new(MockSerial::new(1, 64)));
+  encoder.write(b"first").unwrap();
+  encoder.write(b"second").unwrap();
+  let wire = encoder.sink_mut().inner_mut().sent.split_off(0);
+
+  let mut decoder = Decoder::<2, 4, _, 64>::new(SerialSource::new(MockSerial::new(3, 1)));
+  let mut out = Vec::<u8, 64>::new();
+  let mut msgs = vec![];
+  for b in wire {
+    decoder.source_mut().inner_mut().received.push_back(b);
+    match decoder.read(&mut out) {
+      Ok(()) => msgs.push(out.to_vec()),
+      Err(nb::Error::WouldBlock) => (),
+      Err(nb::Error::Other(e)) => panic!("{:?}", e),
+    }
+  }
+  assert_eq!(msgs, [b"first".to_vec(), b"second".to_vec()]);
+  assert_eq!(decoder.read(&mut out), Err(nb::Error::WouldBlock));
+}
+
+#[test]
+fn port_errors_come_through() {
+  let mut serial = MockSerial::new(8, 8);
+  serial.unplugged = true;
+  let mut encoder = Encoder::<2, 4, _>::new(SerialSink::new(serial));
+  assert_eq!(encoder.write(b"hi"), Err(nb::Error::Other(DencoderError::Transport(Unplugged))));
+
+  let mut serial = MockSerial::new(8, 8);
+  serial.unplugged = true;
+  let mut decoder = Decoder::<2, 4, _, 64>::new(SerialSource::new(serial));
+  let mut out = Vec::<u8, 64>::new();
+  assert_eq!(decoder.read(&mut out), Err(nb::Error::Other(DencoderError::Transport(Unplugged))));
+}
+
+#[test]
+fn bytes_before_an_error_are_kept() {
+  let mut serial = MockSerial::new(3, 8);
+  serial.received.extend(1..=10);
+  serial.unplug_after_reads = Some(2);
+  let mut source = SerialSource::new(serial);
+  let mut buf = [0u8; 10];
+  assert!(matches!(source.rx(&mut buf), Ok(TransmissionStatus::Partial(6))));
+  assert_eq!(buf[..6], [1, 2, 3, 4, 5, 6]);
+  assert!(matches!(source.rx(&mut buf), Err(nb::Error::Other(Unplugged))));
+}
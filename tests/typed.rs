@@ -0,0 +1,69 @@
+use erhannis_misc::dencoder::transport::{BufferSource, VecSink};
+use erhannis_misc::dencoder::typed::{TypedDecoder, TypedEncoder, TypedError};
+use erhannis_misc::dencoder::{Decoder, DencoderError, Encoder};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Command {
+  Stop,
+  Move { x: i16, y: i16 },
+  Beep(u8),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Telemetry {
+  millis: u32,
+  volts: f32,
+}
+
+type Tx<T> = TypedEncoder<Encoder<2, 4, VecSink<512>>, T, 32>;
+type Rx<T> = TypedDecoder<Decoder<2, 4, BufferSource<512>, 512>, T, 32>;
+
+fn pair<S: Serialize, D: serde::de::DeserializeOwned>() -> (Tx<S>, Rx<D>) {
+  (TypedEncoder::new(Encoder::new(VecSink::new())), TypedDecoder::new(Decoder::new_plain()))
+}
+
+fn carry<S: Serialize, D: serde::de::DeserializeOwned>(tx: &mut Tx<S>, rx: &mut Rx<D>) {
+  let bytes = tx.encoder_mut().sink_mut().take();
+  rx.decoder_mut().add(&bytes).unwrap();
+}
+
+#[test]
+fn round_trip() {
+  let (mut tx, mut rx) = pair::<Command, Command>();
+  let cmds = [Command::Move { x: -3, y: 400 }, Command::Stop, Command::Beep(7)];
+  for c in &cmds {
+    tx.send(c).unwrap();
+  }
+  carry(&mut tx, &mut rx);
+  for c in &cmds {
+    assert_eq!(&rx.receive().unwrap(), c);
+  }
+  assert_eq!(rx.receive(), Err(nb::Error::WouldBlock));
+}
+
+#[test]
+fn too_big_to_serialize() {
+  let (mut tx, _) = pair::<[u32; 16], ()>();
+  assert_eq!(tx.send(&[u32::MAX; 16]), Err(nb::Error::Other(TypedError::Serialize(postcard::Error::SerializeBufferFull))));
+  assert!(tx.encoder().sink().as_slice().is_empty());
+}
+
+#[test]
+fn deserialize_failure_is_not_a_checksum_failure() {
+  let (mut tx, mut rx) = pair::<Command, Telemetry>();
+  tx.send(&Command::Move { x: 1, y: 2 }).unwrap();
+  let mut bytes = tx.encoder_mut().sink_mut().take();
+  bytes[7] ^= 0x01;
+  rx.decoder_mut().add(&bytes).unwrap();
+  assert_eq!(rx.receive(), Err(nb::Error::Other(TypedError::Dencoder(DencoderError::MessageChecksum))));
+
+  tx.send(&Command::Stop).unwrap();
+  carry(&mut tx, &mut rx);
+  assert!(matches!(rx.receive(), Err(nb::Error::Other(TypedError::Deserialize(_)))));
+
+  let (mut tx, _) = pair::<Telemetry, ()>();
+  tx.send(&Telemetry { millis: 1234, volts: 3.3 }).unwrap();
+  carry(&mut tx, &mut rx);
+  assert_eq!(rx.receive(), Ok(Telemetry { millis: 1234, volts: 3.3 }));
+}